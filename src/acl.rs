@@ -0,0 +1,575 @@
+//! Decode POSIX access control lists, and check access to inodes the way Linux does.
+
+use alloc::vec::Vec;
+
+use bitflags::bitflags;
+
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::features::MountMode;
+use crate::fs::Ext2;
+use crate::mode::Permissions;
+use crate::schema::{Inode, InodeFlags, InodeNumber};
+
+/// The attribute holding the ACL checked for access to an inode.
+pub const ACL_ACCESS: &str = "system.posix_acl_access";
+
+/// The attribute holding the ACL new files in a directory inherit.
+pub const ACL_DEFAULT: &str = "system.posix_acl_default";
+
+/// The version in the header of an ACL stored by ext2.
+const ACL_VERSION: u32 = 1;
+
+// The tags of the entries of a stored ACL.
+const TAG_USER_OBJ: u16 = 0x01;
+const TAG_USER: u16 = 0x02;
+const TAG_GROUP_OBJ: u16 = 0x04;
+const TAG_GROUP: u16 = 0x08;
+const TAG_MASK: u16 = 0x10;
+const TAG_OTHER: u16 = 0x20;
+
+bitflags! {
+    /// The kinds of access checked by [`Ext2::check_access`], which are also the permissions
+    /// granted by an ACL entry.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Access: u16 {
+        /// Execute a file, or search a directory
+        const EXEC = 0o1;
+        /// Write to a file, or change the entries of a directory
+        const WRITE = 0o2;
+        /// Read a file, or list a directory
+        const READ = 0o4;
+    }
+}
+
+/// Whom an ACL entry applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AclTag {
+    /// The owner of the inode
+    UserObj,
+    /// The user with this ID
+    User(u32),
+    /// The owning group of the inode
+    GroupObj,
+    /// The group with this ID
+    Group(u32),
+    /// The most any `User`, `GroupObj` or `Group` entry may grant
+    Mask,
+    /// Everyone not matched by another entry
+    Other,
+}
+
+/// An entry of an ACL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AclEntry {
+    /// Whom the entry applies to
+    pub tag: AclTag,
+    /// The access the entry grants
+    pub perm: Access,
+}
+
+/// A POSIX access control list, as stored in the [`ACL_ACCESS`] and [`ACL_DEFAULT`] attributes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Acl {
+    /// The entries, in the order they are stored
+    pub entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// Decode an ACL from the format ext2 stores it in: a version number, then entries which
+    /// only hold an ID if their tag needs one.
+    ///
+    /// Returns `None` if the version is unknown, an entry has an unknown tag, or the data ends
+    /// in the middle of an entry.
+    #[must_use]
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let (version, mut rest) = data.split_first_chunk::<4>()?;
+        if u32::from_le_bytes(*version) != ACL_VERSION {
+            return None;
+        }
+
+        let mut entries = Vec::new();
+        while !rest.is_empty() {
+            let (tag, after) = rest.split_first_chunk::<2>()?;
+            let (perm, after) = after.split_first_chunk::<2>()?;
+            let id = |after: &[u8]| after.first_chunk::<4>().map(|id| u32::from_le_bytes(*id));
+            let (tag, len) = match u16::from_le_bytes(*tag) {
+                TAG_USER_OBJ => (AclTag::UserObj, 4),
+                TAG_USER => (AclTag::User(id(after)?), 8),
+                TAG_GROUP_OBJ => (AclTag::GroupObj, 4),
+                TAG_GROUP => (AclTag::Group(id(after)?), 8),
+                TAG_MASK => (AclTag::Mask, 4),
+                TAG_OTHER => (AclTag::Other, 4),
+                _ => return None,
+            };
+            entries.push(AclEntry {
+                tag,
+                perm: Access::from_bits_retain(u16::from_le_bytes(*perm)),
+            });
+            rest = &rest[len..];
+        }
+        Some(Self { entries })
+    }
+
+    /// Encode the ACL in the format ext2 stores it in.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = ACL_VERSION.to_le_bytes().to_vec();
+        for entry in &self.entries {
+            let (tag, id) = match entry.tag {
+                AclTag::UserObj => (TAG_USER_OBJ, None),
+                AclTag::User(uid) => (TAG_USER, Some(uid)),
+                AclTag::GroupObj => (TAG_GROUP_OBJ, None),
+                AclTag::Group(gid) => (TAG_GROUP, Some(gid)),
+                AclTag::Mask => (TAG_MASK, None),
+                AclTag::Other => (TAG_OTHER, None),
+            };
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&entry.perm.bits().to_le_bytes());
+            if let Some(id) = id {
+                data.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    /// Whether the ACL is well formed, as Linux requires before storing one: exactly one
+    /// `UserObj`, `GroupObj` and `Other` entry, in the order of [`AclTag`], a `Mask` entry if
+    /// there are `User` or `Group` entries, and no permissions other than [`Access`].
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        #[derive(PartialEq)]
+        enum State {
+            UserObj,
+            Users,
+            Groups,
+            Other,
+            Done,
+        }
+
+        let mut state = State::UserObj;
+        let mut needs_mask = false;
+        for entry in &self.entries {
+            if !Access::all().contains(entry.perm) {
+                return false;
+            }
+            state = match (entry.tag, state) {
+                (AclTag::UserObj, State::UserObj) => State::Users,
+                (AclTag::User(_), State::Users) => {
+                    needs_mask = true;
+                    State::Users
+                }
+                (AclTag::GroupObj, State::Users) => State::Groups,
+                (AclTag::Group(_), State::Groups) => {
+                    needs_mask = true;
+                    State::Groups
+                }
+                (AclTag::Mask, State::Groups) => State::Other,
+                (AclTag::Other, State::Other) => State::Done,
+                (AclTag::Other, State::Groups) if !needs_mask => State::Done,
+                _ => return false,
+            };
+        }
+        state == State::Done
+    }
+
+    /// `mode` with its permission bits set to what the ACL grants the owner, the owning group
+    /// and others, and whether the ACL grants nothing more than that, as decided by Linux's
+    /// `posix_acl_equiv_mode`.
+    ///
+    /// The group bits come from the `Mask` entry if there is one, as it limits the group.
+    fn equiv_mode(&self, mode: u16) -> (u16, bool) {
+        let mut bits = 0;
+        let mut equivalent = true;
+        for entry in &self.entries {
+            let perm = entry.perm.bits();
+            match entry.tag {
+                AclTag::UserObj => bits |= perm << 6,
+                AclTag::GroupObj => bits |= perm << 3,
+                AclTag::Mask => {
+                    bits = (bits & !0o070) | perm << 3;
+                    equivalent = false;
+                }
+                AclTag::Other => bits |= perm,
+                AclTag::User(_) | AclTag::Group(_) => equivalent = false,
+            }
+        }
+        ((mode & !0o777) | bits, equivalent)
+    }
+
+    /// Whether the ACL of `inode` grants `want` to the user `uid` in the groups `gids`, who does
+    /// not own the inode.
+    ///
+    /// The first entry naming the user, or the first group entry granting `want` to one of
+    /// their groups, decides, limited by the mask. `Other` only applies to users in none of the
+    /// named groups.
+    fn permits(&self, inode: &Inode, uid: u32, gids: &[u32], want: Access) -> bool {
+        let mut in_group = false;
+        for (index, entry) in self.entries.iter().enumerate() {
+            let masked = match entry.tag {
+                AclTag::UserObj if uid == inode.owner_uid() => false,
+                AclTag::User(id) if id == uid => true,
+                AclTag::GroupObj | AclTag::Group(_) => {
+                    let gid = match entry.tag {
+                        AclTag::Group(gid) => gid,
+                        _ => inode.owner_gid(),
+                    };
+                    if !gids.contains(&gid) {
+                        continue;
+                    }
+                    in_group = true;
+                    if !entry.perm.contains(want) {
+                        continue;
+                    }
+                    true
+                }
+                AclTag::Other if in_group => return false,
+                AclTag::Other => false,
+                _ => continue,
+            };
+
+            let mask = self.entries[index + 1..]
+                .iter()
+                .find(|entry| entry.tag == AclTag::Mask)
+                .map_or(Access::all(), |mask| mask.perm);
+            let perm = if masked {
+                entry.perm & mask
+            } else {
+                entry.perm
+            };
+            return perm.contains(want);
+        }
+        false
+    }
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// The access ACL of inode number `number`, or `None` if it has none.
+    ///
+    /// # Errors
+    /// If the inode or its attributes cannot be read, or the ACL is malformed.
+    pub fn access_acl(&self, number: InodeNumber) -> Result<Option<Acl>> {
+        self.read_acl(number, ACL_ACCESS)
+    }
+
+    /// The default ACL of the directory at inode number `number`, or `None` if it has none.
+    ///
+    /// # Errors
+    /// If the inode or its attributes cannot be read, or the ACL is malformed.
+    pub fn default_acl(&self, number: InodeNumber) -> Result<Option<Acl>> {
+        self.read_acl(number, ACL_DEFAULT)
+    }
+
+    /// Check whether the user `uid`, in the groups `gids`, may have `access` to inode number
+    /// `number`.
+    ///
+    /// As in Linux, the owner gets the owner permission bits. Other users get the first
+    /// matching entry of the inode's access ACL, if it has one and its group permission bits
+    /// are not all clear, and otherwise the group or other permission bits. `gids` must include
+    /// the user's primary group. User 0 is treated as the superuser, who may do anything except
+    /// execute a file with no execute bit set.
+    ///
+    /// # Errors
+    /// - [`Error::AccessDenied`] if the access is not allowed.
+    /// - [`Error::ReadOnly`] if `access` includes writing and the filesystem is read-only.
+    /// - [`Error::NotPermitted`] if `access` includes writing and the inode is immutable.
+    /// - If the inode or its access ACL cannot be read, or the ACL is malformed.
+    pub fn check_access(
+        &self,
+        number: InodeNumber,
+        uid: u32,
+        gids: &[u32],
+        access: Access,
+    ) -> Result<()> {
+        let inode = self.inode(number)?;
+        if access.contains(Access::WRITE) {
+            let content = inode.is_file() || inode.is_dir() || inode.is_symlink();
+            if content && self.mount_mode() == MountMode::ReadOnly {
+                return Err(Error::ReadOnly);
+            }
+            if inode.inode_flags().contains(InodeFlags::IMMUTABLE) {
+                return Err(Error::NotPermitted(number));
+            }
+        }
+
+        let mode = inode.type_perm.get();
+        let granted = if uid == inode.owner_uid() {
+            Access::from_bits_truncate(mode >> 6).contains(access)
+        } else {
+            let acl = if mode & 0o070 == 0 {
+                None
+            } else {
+                self.access_acl(number)?
+            };
+            match acl {
+                Some(acl) => acl.permits(&inode, uid, gids, access),
+                None if gids.contains(&inode.owner_gid()) => {
+                    Access::from_bits_truncate(mode >> 3).contains(access)
+                }
+                None => Access::from_bits_truncate(mode).contains(access),
+            }
+        };
+
+        // The superuser may execute only what someone may, but directories may always be
+        // searched.
+        let overridden =
+            uid == 0 && (inode.is_dir() || !access.contains(Access::EXEC) || mode & 0o111 != 0);
+        if granted || overridden {
+            Ok(())
+        } else {
+            Err(Error::AccessDenied(number))
+        }
+    }
+
+    /// Set the permission bits of `inode` from `acl`, which is becoming its access ACL, as
+    /// Linux's `posix_acl_update_mode` does, returning whether the bits say all the ACL does so
+    /// it need not be stored.
+    ///
+    /// The set-group-ID bit is cleared unless the filesystem's credentials are in the inode's
+    /// group or the superuser's.
+    pub(crate) fn apply_access_acl(&self, inode: &mut Inode, acl: &Acl) -> bool {
+        let (mut mode, equivalent) = acl.equiv_mode(inode.type_perm.get());
+        let credentials = self.credentials();
+        if credentials.uid != 0 && credentials.gid != inode.owner_gid() {
+            mode &= !Permissions::SET_GID.bits();
+        }
+        inode.type_perm.set(mode);
+        equivalent
+    }
+
+    /// The ACL stored in the attribute `name` of inode number `number`, if it has one.
+    fn read_acl(&self, number: InodeNumber, name: &str) -> Result<Option<Acl>> {
+        match self.getxattr(number, name) {
+            Ok(value) => Acl::from_bytes(&value)
+                .map(Some)
+                .ok_or(Error::CorruptInode(number)),
+            Err(Error::NoAttribute) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{Credentials, Filesystem};
+    use crate::schema::EXT2_ROOT_INO;
+    use alloc::vec;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    /// `user::rw-,user:1000:rw-,group::r--,group:2000:r-x,mask::r--,other::---`, as stored by
+    /// e2fsprogs.
+    const STORED: [u8; 36] = [
+        1, 0, 0, 0, 1, 0, 6, 0, 2, 0, 6, 0, 0xe8, 3, 0, 0, 4, 0, 4, 0, 8, 0, 5, 0, 0xd0, 7, 0, 0,
+        0x10, 0, 4, 0, 0x20, 0, 0, 0,
+    ];
+
+    const RW: Access = Access::READ.union(Access::WRITE);
+
+    fn entry(tag: AclTag, perm: Access) -> AclEntry {
+        AclEntry { tag, perm }
+    }
+
+    /// Give `/hello.txt` the owner 1000:100 and the permission bits `perm`.
+    fn chown_hello(fs: &mut Ext2<&mut [u8]>, perm: u16) {
+        let mut inode = fs.inode(14).unwrap();
+        inode.set_owner(1000, 100);
+        inode.type_perm.set(0o100_000 | perm);
+        fs.write_inode(14, &inode).unwrap();
+    }
+
+    #[test]
+    fn decodes_the_stored_format() {
+        let acl = Acl::from_bytes(&STORED).unwrap();
+        assert_eq!(
+            acl.entries,
+            [
+                entry(AclTag::UserObj, RW),
+                entry(AclTag::User(1000), RW),
+                entry(AclTag::GroupObj, Access::READ),
+                entry(AclTag::Group(2000), Access::READ | Access::EXEC),
+                entry(AclTag::Mask, Access::READ),
+                entry(AclTag::Other, Access::empty()),
+            ]
+        );
+        assert!(acl.is_valid());
+        assert_eq!(acl.to_bytes(), STORED);
+    }
+
+    #[test]
+    fn rejects_malformed_acls() {
+        let mut version = STORED;
+        version[0] = 2;
+        assert_eq!(Acl::from_bytes(&version), None);
+        assert_eq!(Acl::from_bytes(&STORED[..14]), None);
+        assert_eq!(Acl::from_bytes(&[1, 0, 0, 0, 3, 0, 0, 0]), None);
+
+        let unmasked = Acl {
+            entries: vec![
+                entry(AclTag::UserObj, RW),
+                entry(AclTag::User(1000), RW),
+                entry(AclTag::GroupObj, Access::READ),
+                entry(AclTag::Other, Access::empty()),
+            ],
+        };
+        assert!(!unmasked.is_valid());
+        let unordered = Acl {
+            entries: vec![
+                entry(AclTag::GroupObj, Access::READ),
+                entry(AclTag::UserObj, RW),
+                entry(AclTag::Other, Access::empty()),
+            ],
+        };
+        assert!(!unordered.is_valid());
+
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        assert_eq!(
+            fs.setxattr(14, ACL_ACCESS, unmasked.to_bytes()),
+            Err(Error::InvalidAcl)
+        );
+        // Only directories have default ACLs.
+        assert_eq!(
+            fs.setxattr(14, ACL_DEFAULT, STORED),
+            Err(Error::AccessDenied(14))
+        );
+        assert_eq!(fs.default_acl(14).unwrap(), None);
+        fs.setxattr(1281, ACL_DEFAULT, STORED).unwrap();
+        assert_eq!(fs.default_acl(1281).unwrap(), Acl::from_bytes(&STORED));
+        assert_eq!(fs.access_acl(1281).unwrap(), None);
+    }
+
+    #[test]
+    fn sets_the_mode_from_the_access_acl() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        chown_hello(&mut fs, 0o2666);
+        let minimal = Acl {
+            entries: vec![
+                entry(AclTag::UserObj, Access::READ),
+                entry(AclTag::GroupObj, Access::READ),
+                entry(AclTag::Other, Access::empty()),
+            ],
+        };
+
+        // An ACL the permission bits can express is not stored.
+        fs.setxattr(14, ACL_ACCESS, minimal.to_bytes()).unwrap();
+        assert_eq!(fs.inode(14).unwrap().type_perm.get(), 0o102_440);
+        assert_eq!(fs.access_acl(14).unwrap(), None);
+        assert_eq!(
+            fs.check_access(14, 1000, &[100], Access::WRITE),
+            Err(Error::AccessDenied(14))
+        );
+
+        // Otherwise the group bits come from the mask. The owner is not in the group, so the
+        // set-group-ID bit is cleared.
+        fs.set_credentials(Credentials {
+            uid: 1000,
+            gid: 200,
+        });
+        fs.setxattr(14, ACL_ACCESS, STORED).unwrap();
+        assert_eq!(fs.inode(14).unwrap().type_perm.get(), 0o100_640);
+        assert_eq!(fs.access_acl(14).unwrap(), Acl::from_bytes(&STORED));
+        assert_eq!(fs.check_access(14, 1000, &[100], RW), Ok(()));
+
+        // Setting an equivalent ACL drops the stored one.
+        fs.setxattr(14, ACL_ACCESS, minimal.to_bytes()).unwrap();
+        assert_eq!(fs.access_acl(14).unwrap(), None);
+        assert!(fs.listxattr(14).unwrap().is_empty());
+    }
+
+    #[test]
+    fn checks_permission_bits() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        chown_hello(&mut fs, 0o640);
+
+        assert_eq!(fs.check_access(14, 1000, &[100], RW), Ok(()));
+        assert_eq!(
+            fs.check_access(14, 1000, &[100], Access::EXEC),
+            Err(Error::AccessDenied(14))
+        );
+        assert_eq!(fs.check_access(14, 1001, &[100], Access::READ), Ok(()));
+        assert_eq!(
+            fs.check_access(14, 1001, &[100], Access::WRITE),
+            Err(Error::AccessDenied(14))
+        );
+        assert_eq!(
+            fs.check_access(14, 1001, &[101], Access::READ),
+            Err(Error::AccessDenied(14))
+        );
+
+        // The superuser may read and write anything, but only execute executables.
+        assert_eq!(fs.check_access(14, 0, &[0], RW), Ok(()));
+        assert_eq!(
+            fs.check_access(14, 0, &[0], Access::EXEC),
+            Err(Error::AccessDenied(14))
+        );
+        chown_hello(&mut fs, 0o740);
+        assert_eq!(fs.check_access(14, 0, &[0], Access::EXEC), Ok(()));
+        assert_eq!(
+            fs.check_access(EXT2_ROOT_INO, 0, &[0], Access::all()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn checks_acl_entries() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        chown_hello(&mut fs, 0o640);
+        let acl = Acl {
+            entries: vec![
+                entry(AclTag::UserObj, RW),
+                entry(AclTag::User(2000), RW),
+                entry(AclTag::GroupObj, Access::READ),
+                entry(AclTag::Group(3000), RW),
+                entry(AclTag::Mask, Access::READ),
+                entry(AclTag::Other, Access::empty()),
+            ],
+        };
+        fs.setxattr(14, ACL_ACCESS, acl.to_bytes()).unwrap();
+
+        // Named entries are limited by the mask.
+        assert_eq!(fs.check_access(14, 2000, &[], Access::READ), Ok(()));
+        assert_eq!(
+            fs.check_access(14, 2000, &[], Access::WRITE),
+            Err(Error::AccessDenied(14))
+        );
+        assert_eq!(fs.check_access(14, 4000, &[3000], Access::READ), Ok(()));
+        assert_eq!(
+            fs.check_access(14, 4000, &[3000], Access::WRITE),
+            Err(Error::AccessDenied(14))
+        );
+        assert_eq!(fs.check_access(14, 4000, &[100], Access::READ), Ok(()));
+        assert_eq!(
+            fs.check_access(14, 4000, &[5000], Access::READ),
+            Err(Error::AccessDenied(14))
+        );
+        // The owner is only bound by the owner bits.
+        assert_eq!(fs.check_access(14, 1000, &[], RW), Ok(()));
+
+        // Without group bits, the ACL is not consulted.
+        chown_hello(&mut fs, 0o604);
+        assert_eq!(
+            fs.check_access(14, 2000, &[], Access::WRITE),
+            Err(Error::AccessDenied(14))
+        );
+        assert_eq!(fs.check_access(14, 2000, &[], Access::READ), Ok(()));
+    }
+
+    #[test]
+    fn refuses_writes_to_immutable_inodes() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.set_inode_flags(14, InodeFlags::IMMUTABLE).unwrap();
+
+        assert_eq!(
+            fs.check_access(14, 0, &[0], Access::WRITE),
+            Err(Error::NotPermitted(14))
+        );
+        let fs = Filesystem::new(&image[..]).unwrap();
+        assert_eq!(fs.check_access(14, 0, &[0], Access::READ), Ok(()));
+    }
+}
@@ -0,0 +1,217 @@
+//! Allocate and free blocks using the block group bitmaps.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bitmap;
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::schema::InodeNumber;
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Allocate a block for inode number `inode`, returning its number.
+    ///
+    /// The search starts at `goal` if it is given and inside the filesystem, and otherwise at the
+    /// start of the inode's block group. It continues through the following groups, wrapping
+    /// around, so blocks are placed near the goal when possible.
+    ///
+    /// Unless the filesystem's [`Credentials`](crate::Credentials) may use the reserved blocks,
+    /// the last `r_blocks_count` free blocks are not handed out.
+    ///
+    /// # Errors
+    /// - [`Error::NoSpace`] if no block is available to the caller.
+    /// - [`Error::CorruptBitmap`] if a group's bitmap disagrees with its free block count.
+    /// - If the bitmaps cannot be read, or the bitmaps and counts cannot be written.
+    pub fn allocate_block(&mut self, inode: InodeNumber, goal: Option<u32>) -> Result<u32> {
+        let sb = self.superblock();
+        let mut free = u64::from(sb.free_blocks_count.get());
+        if !self.credentials().may_use_reserved(sb) {
+            free = free.saturating_sub(u64::from(sb.r_blocks_count.get()));
+        }
+        if free == 0 {
+            return Err(Error::NoSpace);
+        }
+
+        let first = sb.first_data_block.get();
+        let per_group = sb.blocks_per_group.get();
+        let goal = match goal {
+            Some(goal) if self.check_block(goal).is_ok() => goal,
+            _ => first + self.inode_group(inode)? * per_group,
+        };
+        let goal_group = (goal - first) / per_group;
+        let goal_bit = ((goal - first) % per_group) as usize;
+
+        // The goal group is visited twice: first from the goal, then from its start.
+        let count = self.group_count();
+        for i in 0..=count {
+            let group = (goal_group + i) % count;
+            if self.groups()[group as usize].free_blocks_count.get() == 0 {
+                continue;
+            }
+            let start = if i == 0 { goal_bit } else { 0 };
+            if let Some(block) = self.allocate_in_group(group, start)? {
+                return Ok(block);
+            }
+        }
+        Err(Error::NoSpace)
+    }
+
+    /// Free block `block`, making it available for allocation again.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidBlock`] if `block` is outside the filesystem.
+    /// - [`Error::CorruptBitmap`] if `block` is already free.
+    /// - If the bitmap cannot be read, or the bitmap and counts cannot be written.
+    pub fn free_block(&mut self, block: u32) -> Result<()> {
+        self.check_block(block)?;
+        let sb = self.superblock();
+        let relative = block - sb.first_data_block.get();
+        let group = relative / sb.blocks_per_group.get();
+        let bit = (relative % sb.blocks_per_group.get()) as usize;
+
+        let mut bits = self.block_bitmap(group)?;
+        if !bitmap::test(&bits, bit) {
+            return Err(Error::CorruptBitmap { group });
+        }
+        bitmap::clear(&mut bits, bit);
+        self.write_block_bitmap(group, &bits)?;
+        self.adjust_free_blocks(group, 1)
+    }
+
+    /// Allocate the first free block in `group` at or after bit `start`, if there is one.
+    fn allocate_in_group(&mut self, group: u32, start: usize) -> Result<Option<u32>> {
+        let len = self.blocks_in_group(group) as usize;
+        let mut bits = self.block_bitmap(group)?;
+        let Some(bit) = bitmap::find_clear(&bits, start, len) else {
+            if start == 0 {
+                return Err(Error::CorruptBitmap { group });
+            }
+            return Ok(None);
+        };
+
+        bitmap::set(&mut bits, bit);
+        self.write_block_bitmap(group, &bits)?;
+        self.adjust_free_blocks(group, -1)?;
+
+        let sb = self.superblock();
+        let block = sb.first_data_block.get() + group * sb.blocks_per_group.get() + bit as u32;
+        Ok(Some(block))
+    }
+
+    /// The number of blocks in `group`, which is smaller than `blocks_per_group` for the last.
+    fn blocks_in_group(&self, group: u32) -> u32 {
+        let sb = self.superblock();
+        let start = sb.first_data_block.get() + group * sb.blocks_per_group.get();
+        (sb.blocks_count.get() - start).min(sb.blocks_per_group.get())
+    }
+
+    fn block_bitmap(&self, group: u32) -> Result<Vec<u8>> {
+        let mut bits = vec![0; self.block_size()];
+        let block = self.groups()[group as usize].block_usage_addr.get();
+        self.read_block_at(block, 0, &mut bits)?;
+        Ok(bits)
+    }
+
+    fn write_block_bitmap(&mut self, group: u32, bits: &[u8]) -> Result<()> {
+        let block = self.groups()[group as usize].block_usage_addr.get();
+        self.write_block_at(block, 0, bits)
+    }
+
+    /// Add `delta` to the free block counts of `group` and the superblock.
+    fn adjust_free_blocks(&mut self, group: u32, delta: i32) -> Result<()> {
+        let mut desc = self.groups()[group as usize].clone();
+        let free = desc
+            .free_blocks_count
+            .get()
+            .wrapping_add_signed(delta as i16);
+        desc.free_blocks_count.set(free);
+        self.write_group(group, desc)?;
+
+        let mut superblock = self.superblock().clone();
+        let free = superblock
+            .free_blocks_count
+            .get()
+            .wrapping_add_signed(delta);
+        superblock.free_blocks_count.set(free);
+        self.write_superblock(superblock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{Credentials, Filesystem};
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn allocates_in_the_inodes_group() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+
+        assert_eq!(fs.allocate_block(14, None).unwrap(), 378);
+        assert_eq!(fs.allocate_block(14, None).unwrap(), 379);
+        assert_eq!(fs.allocate_block(1281, None).unwrap(), 8557);
+
+        let fs = Filesystem::new(&image[..]).unwrap();
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496 - 3);
+        assert_eq!(fs.groups()[0].free_blocks_count.get(), 7814 - 2);
+        assert_eq!(fs.groups()[1].free_blocks_count.get(), 1682 - 1);
+    }
+
+    #[test]
+    fn allocates_near_the_goal() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+
+        assert_eq!(fs.allocate_block(14, Some(9000)).unwrap(), 9000);
+        assert_eq!(fs.allocate_block(14, Some(9000)).unwrap(), 9001);
+        assert_eq!(fs.allocate_block(14, Some(517)).unwrap(), 518);
+        assert_eq!(fs.allocate_block(14, Some(20_000)).unwrap(), 378);
+    }
+
+    #[test]
+    fn frees_blocks() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let block = fs.allocate_block(14, None).unwrap();
+        fs.free_block(block).unwrap();
+
+        assert_eq!(
+            fs.free_block(block).unwrap_err(),
+            Error::CorruptBitmap { group: 0 }
+        );
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496);
+        assert_eq!(fs.groups()[0].free_blocks_count.get(), 7814);
+        assert_eq!(fs.allocate_block(14, None).unwrap(), block);
+    }
+
+    #[test]
+    fn keeps_reserved_blocks_for_root() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut superblock = fs.superblock().clone();
+        superblock.free_blocks_count.set(512);
+        fs.write_superblock(superblock).unwrap();
+
+        fs.set_credentials(Credentials {
+            uid: 1000,
+            gid: 1000,
+        });
+        assert_eq!(fs.allocate_block(14, None).unwrap_err(), Error::NoSpace);
+        fs.set_credentials(Credentials { uid: 1000, gid: 0 });
+        assert_eq!(fs.allocate_block(14, None).unwrap_err(), Error::NoSpace);
+        fs.set_credentials(Credentials::ROOT);
+        assert_eq!(fs.allocate_block(14, None).unwrap(), 378);
+
+        let mut superblock = fs.superblock().clone();
+        superblock.block_gid.set(1000);
+        fs.write_superblock(superblock).unwrap();
+        fs.set_credentials(Credentials {
+            uid: 1000,
+            gid: 1000,
+        });
+        assert_eq!(fs.allocate_block(14, None).unwrap(), 379);
+    }
+}
@@ -0,0 +1,59 @@
+//! Bit operations on the block and inode usage bitmaps.
+
+/// Whether bit `bit` of `bitmap` is set.
+pub(crate) fn test(bitmap: &[u8], bit: usize) -> bool {
+    bitmap[bit / 8] & (1 << (bit % 8)) != 0
+}
+
+/// Set bit `bit` of `bitmap`.
+pub(crate) fn set(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] |= 1 << (bit % 8);
+}
+
+/// Clear bit `bit` of `bitmap`.
+pub(crate) fn clear(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] &= !(1 << (bit % 8));
+}
+
+/// Find the first clear bit of `bitmap` in `start..end`.
+pub(crate) fn find_clear(bitmap: &[u8], start: usize, end: usize) -> Option<usize> {
+    let mut bit = start;
+    while bit < end {
+        let byte = bitmap[bit / 8];
+        // Skip whole bytes which are full.
+        if bit.is_multiple_of(8) && byte == 0xff {
+            bit += 8;
+            continue;
+        }
+        if byte & (1 << (bit % 8)) == 0 {
+            return Some(bit);
+        }
+        bit += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_and_clears_bits() {
+        let mut bitmap = [0; 2];
+        set(&mut bitmap, 9);
+
+        assert_eq!(bitmap, [0, 2]);
+        assert!(test(&bitmap, 9));
+        clear(&mut bitmap, 9);
+        assert!(!test(&bitmap, 9));
+    }
+
+    #[test]
+    fn finds_clear_bits() {
+        let bitmap = [0xff, 0b1011_1111, 0xff];
+
+        assert_eq!(find_clear(&bitmap, 0, 24), Some(14));
+        assert_eq!(find_clear(&bitmap, 15, 24), None);
+        assert_eq!(find_clear(&bitmap, 0, 14), None);
+    }
+}
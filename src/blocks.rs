@@ -0,0 +1,457 @@
+//! Map the logical blocks of a file to blocks on disk.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::schema::{Inode, InodeNumber};
+
+/// The number of block pointers stored directly in an inode.
+pub const DIRECT_BLOCKS: u32 = 12;
+
+/// The route through the block pointer tree to a logical block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BlockPath {
+    /// The index into the inode's block pointers.
+    pub root: usize,
+    /// The index into each level of indirect blocks, outermost first.
+    pub offsets: [usize; 3],
+    /// How many levels of indirect blocks are traversed.
+    pub depth: usize,
+}
+
+impl BlockPath {
+    /// Find the path to logical block `logical`, given the number of pointers per indirect block.
+    ///
+    /// Returns `None` if the block is past the largest file the pointer tree can describe.
+    pub fn new(logical: u32, per_block: u32) -> Option<Self> {
+        let per = u64::from(per_block);
+        let mut rest = u64::from(logical);
+        let mut path = Self {
+            root: 0,
+            offsets: [0; 3],
+            depth: 0,
+        };
+
+        if rest < u64::from(DIRECT_BLOCKS) {
+            path.root = rest as usize;
+            return Some(path);
+        }
+        rest -= u64::from(DIRECT_BLOCKS);
+
+        let mut span = 1;
+        for depth in 1..=3 {
+            span *= per;
+            if rest < span {
+                path.root = DIRECT_BLOCKS as usize + depth - 1;
+                path.depth = depth;
+                for level in (0..depth).rev() {
+                    path.offsets[level] = (rest % per) as usize;
+                    rest /= per;
+                }
+                return Some(path);
+            }
+            rest -= span;
+        }
+        None
+    }
+}
+
+/// The most recently read block at each level of indirection.
+#[derive(Debug, Default)]
+pub(crate) struct IndirectCache {
+    levels: [Option<(u32, Vec<u8>)>; 3],
+}
+
+impl Inode {
+    /// The inode's 15 block pointers: 12 direct, then the singly, doubly and triply indirect.
+    #[must_use]
+    pub fn block_pointers(&self) -> [u32; 15] {
+        let mut pointers = [0; 15];
+        for (pointer, direct) in pointers.iter_mut().zip(&self.direct_pointer) {
+            *pointer = direct.get();
+        }
+        pointers[12] = self.indirect_pointer.get();
+        pointers[13] = self.doubly_indirect.get();
+        pointers[14] = self.triply_indirect.get();
+        pointers
+    }
+
+    /// Set block pointer `index`, numbered as in [`Inode::block_pointers`].
+    ///
+    /// # Panics
+    /// If `index` is 15 or more.
+    pub fn set_block_pointer(&mut self, index: usize, block: u32) {
+        match index {
+            0..=11 => self.direct_pointer[index].set(block),
+            12 => self.indirect_pointer.set(block),
+            13 => self.doubly_indirect.set(block),
+            14 => self.triply_indirect.set(block),
+            _ => panic!("block pointer index {index} out of range"),
+        }
+    }
+
+    /// Set the size of the file, in bytes.
+    ///
+    /// Only regular files store the upper 32 bits of the size, in `size_high`.
+    pub fn set_size(&mut self, size: u64) {
+        self.size_low.set(size as u32);
+        if self.is_file() {
+            self.size_high.set((size >> 32) as u32);
+        }
+    }
+
+    /// The size of the file, in bytes.
+    ///
+    /// For regular files, `size_high` holds the upper 32 bits of the size.
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        if self.is_file() {
+            (self.size_high.get() as u64) << 32 | self.size_low.get() as u64
+        } else {
+            self.size_low.get() as u64
+        }
+    }
+
+    /// Find the block on disk holding logical block `logical` of this file.
+    ///
+    /// Returns `None` if the block is a hole, or is past the largest file the block pointers can
+    /// describe.
+    ///
+    /// # Errors
+    /// If an indirect block cannot be read, or a block pointer is past the end of the filesystem.
+    pub fn block_map<D: BlockDevice>(&self, fs: &Ext2<D>, logical: u32) -> Result<Option<u32>> {
+        self.block_map_cached(fs, logical, &mut IndirectCache::default())
+    }
+
+    /// Iterate over the blocks of the file, from logical block 0 up to its size.
+    ///
+    /// Each item is the block on disk holding that logical block, or `None` for a hole.
+    #[must_use]
+    pub fn blocks<'a, D: BlockDevice>(&'a self, fs: &'a Ext2<D>) -> Blocks<'a, D> {
+        let block_size = fs.block_size() as u64;
+        let end = self.size().div_ceil(block_size);
+        Blocks {
+            fs,
+            inode: self,
+            next: 0,
+            end: u32::try_from(end).unwrap_or(u32::MAX),
+            cache: IndirectCache::default(),
+        }
+    }
+
+    pub(crate) fn block_map_cached<D: BlockDevice>(
+        &self,
+        fs: &Ext2<D>,
+        logical: u32,
+        cache: &mut IndirectCache,
+    ) -> Result<Option<u32>> {
+        let per_block = (fs.block_size() / 4) as u32;
+        let Some(path) = BlockPath::new(logical, per_block) else {
+            return Ok(None);
+        };
+
+        let mut block = self.block_pointers()[path.root];
+        for (level, &offset) in path.offsets[..path.depth].iter().enumerate() {
+            if block == 0 {
+                return Ok(None);
+            }
+            fs.check_block(block)?;
+
+            let entry = &mut cache.levels[level];
+            if entry.as_ref().is_none_or(|(cached, _)| *cached != block) {
+                let mut bytes = vec![0; fs.block_size()];
+                fs.read_block_at(block, 0, &mut bytes)?;
+                *entry = Some((block, bytes));
+            }
+            let bytes = &entry.as_ref().expect("entry was filled").1;
+            let pointer = &bytes[offset * 4..offset * 4 + 4];
+            block = u32::from_le_bytes(pointer.try_into().expect("pointer is 4 bytes"));
+        }
+
+        if block == 0 {
+            return Ok(None);
+        }
+        fs.check_block(block)?;
+        Ok(Some(block))
+    }
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Check that `block` is inside the filesystem.
+    pub(crate) fn check_block(&self, block: u32) -> Result<()> {
+        let sb = self.superblock();
+        if block < sb.first_data_block.get() || block >= sb.blocks_count.get() {
+            return Err(Error::InvalidBlock(block));
+        }
+        Ok(())
+    }
+
+    /// Find the block holding logical block `logical` of inode number `number`, allocating it,
+    /// and any indirect blocks leading to it, if it is a hole.
+    ///
+    /// Returns the block and whether it was just allocated. New indirect blocks are zeroed, but
+    /// new data blocks are not. The block pointers and `sectors_count` of `inode` are updated,
+    /// and the caller must write it back.
+    pub(crate) fn map_or_allocate(
+        &mut self,
+        number: InodeNumber,
+        inode: &mut Inode,
+        logical: u32,
+        goal: Option<u32>,
+    ) -> Result<(u32, bool)> {
+        let per_block = (self.block_size() / 4) as u32;
+        let path = BlockPath::new(logical, per_block).ok_or(Error::FileTooLarge)?;
+
+        let mut block = inode.block_pointers()[path.root];
+        let mut fresh = block == 0;
+        if fresh {
+            block = self.allocate_tree_block(number, inode, goal, path.depth > 0)?;
+            inode.set_block_pointer(path.root, block);
+        }
+        for (level, &offset) in path.offsets[..path.depth].iter().enumerate() {
+            self.check_block(block)?;
+            let mut pointer = [0; 4];
+            self.read_block_at(block, offset * 4, &mut pointer)?;
+            let mut next = u32::from_le_bytes(pointer);
+
+            fresh = next == 0;
+            if fresh {
+                let indirect = level + 1 < path.depth;
+                next = self.allocate_tree_block(number, inode, goal, indirect)?;
+                self.write_block_at(block, offset * 4, &next.to_le_bytes())?;
+            }
+            block = next;
+        }
+        self.check_block(block)?;
+        Ok((block, fresh))
+    }
+
+    /// Free every block of `inode` from logical block `first` onwards, along with any indirect
+    /// blocks left empty.
+    ///
+    /// The block pointers and `sectors_count` of `inode` are updated, and the caller must write
+    /// it back.
+    pub(crate) fn free_blocks_from(&mut self, inode: &mut Inode, first: u32) -> Result<()> {
+        let per_block = (self.block_size() / 4) as u64;
+        let first = u64::from(first);
+        let mut freed = 0;
+
+        // The first logical block under each of the inode's block pointers.
+        let mut start = 0;
+        for (index, block) in inode.block_pointers().into_iter().enumerate() {
+            let depth = index.saturating_sub(DIRECT_BLOCKS as usize - 1) as u32;
+            let span = per_block.pow(depth);
+            if block != 0 && first <= start {
+                freed += self.free_tree(block, depth)?;
+                inode.set_block_pointer(index, 0);
+            } else if block != 0 && first < start + span {
+                let (count, empty) = self.truncate_tree(block, depth, first - start)?;
+                freed += count;
+                if empty {
+                    self.free_block(block)?;
+                    freed += 1;
+                    inode.set_block_pointer(index, 0);
+                }
+            }
+            start += span;
+        }
+
+        let sectors = freed * self.sectors_per_block();
+        inode
+            .sectors_count
+            .set(inode.sectors_count.get().saturating_sub(sectors));
+        Ok(())
+    }
+
+    /// The number of 512-byte sectors in a block, the unit of `sectors_count`.
+    pub(crate) fn sectors_per_block(&self) -> u32 {
+        (self.block_size() / 512) as u32
+    }
+
+    /// Write zeros over the whole of `block`.
+    pub(crate) fn zero_block(&mut self, block: u32) -> Result<()> {
+        self.write_block_at(block, 0, &vec![0; self.block_size()])
+    }
+
+    /// Allocate a block in the tree of `inode`, counting it in `sectors_count`.
+    fn allocate_tree_block(
+        &mut self,
+        number: InodeNumber,
+        inode: &mut Inode,
+        goal: Option<u32>,
+        indirect: bool,
+    ) -> Result<u32> {
+        let block = self.allocate_block(number, goal)?;
+        if indirect {
+            self.zero_block(block)?;
+        }
+        let sectors = inode.sectors_count.get() + self.sectors_per_block();
+        inode.sectors_count.set(sectors);
+        Ok(block)
+    }
+
+    /// Free `block` and, if it is an indirect block `depth` levels above the data, the blocks
+    /// under it, returning how many blocks were freed.
+    fn free_tree(&mut self, block: u32, depth: u32) -> Result<u32> {
+        self.check_block(block)?;
+        let mut freed = 0;
+        if depth > 0 {
+            for child in self.indirect_pointers(block)? {
+                if child != 0 {
+                    freed += self.free_tree(child, depth - 1)?;
+                }
+            }
+        }
+        self.free_block(block)?;
+        Ok(freed + 1)
+    }
+
+    /// Free the blocks under the indirect block `block`, `depth` levels above the data, from the
+    /// `from`th logical block it covers onwards.
+    ///
+    /// Returns how many blocks were freed, and whether `block` is now empty.
+    fn truncate_tree(&mut self, block: u32, depth: u32, from: u64) -> Result<(u32, bool)> {
+        self.check_block(block)?;
+        let mut pointers = self.indirect_pointers(block)?;
+        let span = (self.block_size() / 4).pow(depth - 1) as u64;
+        let mut freed = 0;
+        let mut changed = false;
+
+        for (index, pointer) in pointers.iter_mut().enumerate() {
+            let start = index as u64 * span;
+            if *pointer == 0 || start + span <= from {
+                continue;
+            }
+            if start >= from {
+                freed += self.free_tree(*pointer, depth - 1)?;
+            } else {
+                let (count, empty) = self.truncate_tree(*pointer, depth - 1, from - start)?;
+                freed += count;
+                if !empty {
+                    continue;
+                }
+                self.free_block(*pointer)?;
+                freed += 1;
+            }
+            *pointer = 0;
+            changed = true;
+        }
+
+        if changed {
+            let bytes: Vec<u8> = pointers.iter().flat_map(|p| p.to_le_bytes()).collect();
+            self.write_block_at(block, 0, &bytes)?;
+        }
+        Ok((freed, pointers.iter().all(|&p| p == 0)))
+    }
+
+    /// Read the block pointers stored in the indirect block `block`.
+    fn indirect_pointers(&self, block: u32) -> Result<Vec<u32>> {
+        let mut bytes = vec![0; self.block_size()];
+        self.read_block_at(block, 0, &mut bytes)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|p| u32::from_le_bytes(p.try_into().expect("chunk is 4 bytes")))
+            .collect())
+    }
+}
+
+/// An iterator over the blocks of a file, created by [`Inode::blocks`].
+#[derive(Debug)]
+pub struct Blocks<'a, D> {
+    fs: &'a Ext2<D>,
+    inode: &'a Inode,
+    next: u32,
+    end: u32,
+    cache: IndirectCache,
+}
+
+impl<D: BlockDevice> Iterator for Blocks<'_, D> {
+    type Item = Result<Option<u32>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            return None;
+        }
+        let logical = self.next;
+        self.next += 1;
+        Some(
+            self.inode
+                .block_map_cached(self.fs, logical, &mut self.cache),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.end - self.next) as usize;
+        (len, Some(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn block_paths() {
+        let path = |logical| BlockPath::new(logical, 256).unwrap();
+
+        assert_eq!((path(11).root, path(11).depth), (11, 0));
+        assert_eq!(
+            (path(12).root, path(12).depth, path(12).offsets[0]),
+            (12, 1, 0)
+        );
+        assert_eq!((path(267).root, path(267).offsets[0]), (12, 255));
+        assert_eq!(path(268).depth, 2);
+        assert_eq!(path(268 + 256 * 256).depth, 3);
+        assert_eq!(path(268 + 256 * 256 + 257).offsets, [0, 1, 1]);
+        assert_eq!(BlockPath::new(268 + 256 * 256 + 256 * 256 * 256, 256), None);
+    }
+
+    #[test]
+    fn maps_direct_blocks() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let hello = fs.inode(14).unwrap();
+
+        assert_eq!(hello.block_map(&fs, 0).unwrap(), Some(517));
+        assert_eq!(hello.block_map(&fs, 1).unwrap(), None);
+        assert_eq!(
+            hello.blocks(&fs).collect::<Result<Vec<_>>>().unwrap(),
+            [Some(517)]
+        );
+    }
+
+    #[test]
+    fn maps_indirect_blocks() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.write_block_at(9000, 3 * 4, &517u32.to_le_bytes())
+            .unwrap();
+
+        let mut inode = fs.inode(14).unwrap();
+        inode.indirect_pointer.set(9000);
+        inode.size_low.set(16 * 1024);
+
+        assert_eq!(inode.block_map(&fs, 12).unwrap(), None);
+        assert_eq!(inode.block_map(&fs, 15).unwrap(), Some(517));
+        let blocks = inode.blocks(&fs).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(blocks.len(), 16);
+        assert_eq!(blocks.iter().flatten().count(), 2);
+    }
+
+    #[test]
+    fn rejects_pointers_outside_filesystem() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let mut inode = fs.inode(14).unwrap();
+        inode.direct_pointer[0].set(20_000);
+
+        assert_eq!(
+            inode.block_map(&fs, 0).unwrap_err(),
+            Error::InvalidBlock(20_000)
+        );
+    }
+}
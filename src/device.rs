@@ -0,0 +1,216 @@
+//! Storage the filesystem can be read from and written to.
+
+use core::fmt;
+
+/// The reasons an access to a [`BlockDevice`] can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeviceError {
+    /// The access extends past the end of the device.
+    OutOfBounds {
+        /// The byte offset the access started at.
+        offset: u64,
+        /// The length of the access, in bytes.
+        len: usize,
+    },
+    /// The device cannot be written to.
+    ReadOnly,
+    /// The device or its driver reported a failure.
+    Io,
+    /// The host operating system reported a failure.
+    #[cfg(feature = "std")]
+    Std(std::io::ErrorKind),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfBounds { offset, len } => {
+                write!(
+                    f,
+                    "access of {len} bytes at offset {offset} is out of bounds"
+                )
+            }
+            Self::ReadOnly => write!(f, "device is read-only"),
+            Self::Io => write!(f, "device I/O error"),
+            #[cfg(feature = "std")]
+            Self::Std(kind) => write!(f, "I/O error: {kind}"),
+        }
+    }
+}
+
+/// A device made up of fixed-size sectors, such as a disk or an image file.
+///
+/// Accesses are addressed by byte offset and need not be sector aligned; implementations for
+/// hardware which requires aligned accesses are responsible for any read-modify-write.
+pub trait BlockDevice {
+    /// The size of a sector, in bytes.
+    fn sector_size(&self) -> usize {
+        512
+    }
+
+    /// The number of sectors on the device.
+    fn block_count(&self) -> u64;
+
+    /// The size of the device, in bytes.
+    fn size(&self) -> u64 {
+        self.block_count() * self.sector_size() as u64
+    }
+
+    /// Whether writes to the device always fail. A filesystem on a read-only device is opened
+    /// read-only.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// Fill `buf` with the bytes starting at `offset`.
+    ///
+    /// # Errors
+    /// If the read extends past the end of the device, or the device fails.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), DeviceError>;
+
+    /// Write all of `buf` to the device starting at `offset`.
+    ///
+    /// # Errors
+    /// If the write extends past the end of the device, or the device fails or is read-only.
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), DeviceError>;
+}
+
+/// Find the range of a slice of length `size` covered by an access.
+fn range(size: usize, offset: u64, len: usize) -> Result<core::ops::Range<usize>, DeviceError> {
+    let err = DeviceError::OutOfBounds { offset, len };
+    let start = usize::try_from(offset).map_err(|_| err)?;
+    let end = start.checked_add(len).ok_or(err)?;
+    if end > size {
+        return Err(err);
+    }
+    Ok(start..end)
+}
+
+impl BlockDevice for &[u8] {
+    fn block_count(&self) -> u64 {
+        (self.len() / self.sector_size()) as u64
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
+        buf.copy_from_slice(&self[range(self.len(), offset, buf.len())?]);
+        Ok(())
+    }
+
+    fn write_at(&mut self, _offset: u64, _buf: &[u8]) -> Result<(), DeviceError> {
+        Err(DeviceError::ReadOnly)
+    }
+}
+
+impl BlockDevice for &mut [u8] {
+    fn block_count(&self) -> u64 {
+        (self.len() / self.sector_size()) as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
+        buf.copy_from_slice(&self[range(self.len(), offset, buf.len())?]);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), DeviceError> {
+        let range = range(self.len(), offset, buf.len())?;
+        self[range].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for DeviceError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Std(err.kind())
+    }
+}
+
+#[cfg(feature = "std")]
+impl BlockDevice for std::fs::File {
+    fn block_count(&self) -> u64 {
+        self.metadata().map_or(0, |m| m.len()) / self.sector_size() as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = self;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf).map_err(|err| match err.kind() {
+            std::io::ErrorKind::UnexpectedEof => DeviceError::OutOfBounds {
+                offset,
+                len: buf.len(),
+            },
+            kind => DeviceError::Std(kind),
+        })
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), DeviceError> {
+        use std::io::{Seek, SeekFrom, Write};
+
+        if offset + buf.len() as u64 > self.size() {
+            return Err(DeviceError::OutOfBounds {
+                offset,
+                len: buf.len(),
+            });
+        }
+        self.seek(SeekFrom::Start(offset))?;
+        self.write_all(buf)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice_reads_at_offset() {
+        let bytes: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7];
+        let mut buf = [0; 3];
+
+        bytes.read_at(2, &mut buf).unwrap();
+        assert_eq!(buf, [2, 3, 4]);
+        assert_eq!(
+            bytes.read_at(6, &mut buf),
+            Err(DeviceError::OutOfBounds { offset: 6, len: 3 })
+        );
+    }
+
+    #[test]
+    fn slice_is_read_only() {
+        let mut bytes: &[u8] = &[0; 512];
+
+        assert_eq!(bytes.block_count(), 1);
+        assert!(bytes.is_read_only());
+        assert_eq!(bytes.write_at(0, &[1]), Err(DeviceError::ReadOnly));
+    }
+
+    #[test]
+    fn mut_slice_writes_at_offset() {
+        let mut storage = [0u8; 1024];
+        let mut bytes = &mut storage[..];
+
+        bytes.write_at(510, &[1, 2, 3]).unwrap();
+        assert!(!bytes.is_read_only());
+        assert_eq!(bytes.block_count(), 2);
+        assert_eq!(bytes[509..514], [0, 1, 2, 3, 0]);
+        assert!(bytes.write_at(1023, &[1, 2]).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn file_reads_image() {
+        let file = std::fs::File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2")).unwrap();
+        let mut magic = [0; 2];
+
+        file.read_at(1024 + 56, &mut magic).unwrap();
+        assert_eq!(u16::from_le_bytes(magic), crate::schema::EXT2_MAGIC);
+        assert_eq!(file.size(), 10 * 1024 * 1024);
+    }
+}
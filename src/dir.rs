@@ -0,0 +1,644 @@
+//! Read directories, and add, remove and change their entries.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::features::IncompatFeatures;
+use crate::fs::Ext2;
+use crate::mode::FileType;
+use crate::schema::{DirectoryEntry, Inode, InodeFlags, InodeNumber, TypeIndicator, EXT2_NAME_LEN};
+
+/// The size of a directory entry header.
+const HEADER_SIZE: usize = size_of::<DirectoryEntry>();
+
+/// The smallest record which can hold a name of length `name_len`.
+pub(crate) const fn record_len(name_len: usize) -> usize {
+    (HEADER_SIZE + name_len + 3) & !3
+}
+
+/// Check that `name` can be stored in a directory entry.
+///
+/// # Errors
+/// - [`Error::InvalidName`] if `name` is empty, or contains a slash or NUL.
+/// - [`Error::NameTooLong`] if `name` is longer than 255 bytes.
+pub(crate) fn check_name(name: &[u8]) -> Result<()> {
+    if name.is_empty() || name.contains(&b'/') || name.contains(&0) {
+        return Err(Error::InvalidName);
+    }
+    if name.len() > EXT2_NAME_LEN {
+        return Err(Error::NameTooLong);
+    }
+    Ok(())
+}
+
+/// A record with room for a new entry, found by [`Dir::find_slot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Slot {
+    /// The offset of the record in the directory.
+    pub offset: usize,
+    /// The bytes used by the entry already in the record, or 0 if the record is deleted.
+    pub used: usize,
+    /// The length of the record.
+    pub len: usize,
+}
+
+/// A live record found by [`Dir::locate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Located {
+    /// The offset of the record in the directory.
+    pub offset: usize,
+    /// The offset of the record before it in the same block, if it is not the first.
+    pub prev: Option<usize>,
+    /// The length of the record.
+    pub len: usize,
+    /// The inode the entry points to.
+    pub inode: InodeNumber,
+}
+
+impl TryFrom<u8> for TypeIndicator {
+    type Error = u8;
+
+    fn try_from(raw: u8) -> Result<Self, u8> {
+        Ok(match raw {
+            0 => Self::Unknown,
+            1 => Self::Regular,
+            2 => Self::Directory,
+            3 => Self::Character,
+            4 => Self::Block,
+            5 => Self::Fifo,
+            6 => Self::Socket,
+            7 => Self::Symlink,
+            _ => return Err(raw),
+        })
+    }
+}
+
+/// An entry in a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirEntry<'a> {
+    /// The inode the entry points to.
+    pub inode: InodeNumber,
+    /// The type of the inode, if the filesystem records it in directory entries.
+    pub file_type: TypeIndicator,
+    /// The name of the entry.
+    pub name: &'a [u8],
+}
+
+/// The contents of a directory, created by [`Ext2::read_dir`].
+#[derive(Debug)]
+pub struct Dir {
+    number: InodeNumber,
+    /// The offset of `data` in the directory, if only part of it was read.
+    base: usize,
+    data: Vec<u8>,
+    block_size: usize,
+    inodes_count: u32,
+    filetype: bool,
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Read the directory at inode number `number`.
+    ///
+    /// # Errors
+    /// If the inode cannot be read or is not a directory, or the directory's blocks cannot be
+    /// read.
+    pub fn read_dir(&self, number: InodeNumber) -> Result<Dir> {
+        let inode = self.inode(number)?;
+        if !inode.is_dir() {
+            return Err(Error::NotADirectory(number));
+        }
+
+        let block_size = self.block_size();
+        let mut data = vec![0; usize::try_from(inode.size()).map_err(|_| Error::FileTooLarge)?];
+        if data.len() % block_size != 0 {
+            return Err(Error::CorruptDirectory { number, offset: 0 });
+        }
+        let chunks = data.chunks_mut(block_size).zip(inode.blocks(self));
+        for (index, (chunk, block)) in chunks.enumerate() {
+            let Some(block) = block? else {
+                let offset = index * block_size;
+                return Err(Error::CorruptDirectory { number, offset });
+            };
+            self.read_block_at(block, 0, chunk)?;
+        }
+
+        Ok(self.dir_from(number, 0, data))
+    }
+
+    /// Read logical block `logical` of the directory at inode number `number`, whose inode is
+    /// `inode`, on its own.
+    ///
+    /// Offsets in the returned [`Dir`] are relative to the start of the block, but errors report
+    /// offsets in the whole directory.
+    pub(crate) fn read_dir_block(
+        &self,
+        number: InodeNumber,
+        inode: &Inode,
+        logical: u32,
+    ) -> Result<Dir> {
+        let block_size = self.block_size();
+        let base = logical as usize * block_size;
+        let block = inode
+            .block_map(self, logical)?
+            .ok_or(Error::CorruptDirectory {
+                number,
+                offset: base,
+            })?;
+        let mut data = vec![0; block_size];
+        self.read_block_at(block, 0, &mut data)?;
+        Ok(self.dir_from(number, base, data))
+    }
+
+    fn dir_from(&self, number: InodeNumber, base: usize, data: Vec<u8>) -> Dir {
+        Dir {
+            number,
+            base,
+            data,
+            block_size: self.block_size(),
+            inodes_count: self.superblock().inodes_count.get(),
+            filetype: self
+                .superblock()
+                .incompat_features()
+                .contains(IncompatFeatures::FILETYPE),
+        }
+    }
+}
+
+impl Dir {
+    /// The number of the directory's inode.
+    #[must_use]
+    pub const fn number(&self) -> InodeNumber {
+        self.number
+    }
+
+    /// Iterate over the entries in the directory, skipping deleted entries.
+    #[must_use]
+    pub fn iter(&self) -> DirIter<'_> {
+        DirIter {
+            dir: self,
+            offset: 0,
+        }
+    }
+
+    /// Find the entry named `name`.
+    ///
+    /// # Errors
+    /// If the directory is malformed before the entry is found.
+    pub fn find(&self, name: &[u8]) -> Result<Option<DirEntry<'_>>> {
+        for entry in self {
+            let entry = entry?;
+            if entry.name == name {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Find the record of the entry named `name`, along with the record before it.
+    ///
+    /// # Errors
+    /// If the directory is malformed before the entry is found.
+    pub(crate) fn locate(&self, name: &[u8]) -> Result<Option<Located>> {
+        let mut offset = 0;
+        let mut prev = None;
+        while offset < self.data.len() {
+            if offset % self.block_size == 0 {
+                prev = None;
+            }
+            let (entry, len) = self.record(offset)?;
+            if let Some(entry) = entry.filter(|entry| entry.name == name) {
+                let inode = entry.inode;
+                return Ok(Some(Located {
+                    offset: self.base + offset,
+                    prev: prev.map(|prev| self.base + prev),
+                    len,
+                    inode,
+                }));
+            }
+            prev = Some(offset);
+            offset += len;
+        }
+        Ok(None)
+    }
+
+    /// Whether the directory has no entries other than `.` and `..`.
+    ///
+    /// # Errors
+    /// If the directory is malformed.
+    pub fn is_empty(&self) -> Result<bool> {
+        for entry in self {
+            if !matches!(entry?.name, b"." | b"..") {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Find a record with room for an entry of `needed` bytes, either in the slack after a live
+    /// entry or in a deleted record.
+    ///
+    /// # Errors
+    /// If the directory is malformed before a slot is found.
+    pub(crate) fn find_slot(&self, needed: usize) -> Result<Option<Slot>> {
+        let mut offset = 0;
+        while offset < self.data.len() {
+            let (entry, len) = self.record(offset)?;
+            let used = entry.map_or(0, |entry| record_len(entry.name.len()));
+            if len - used >= needed {
+                return Ok(Some(Slot { offset, used, len }));
+            }
+            offset += len;
+        }
+        Ok(None)
+    }
+
+    /// Parse the record at `offset`, returning it if it is in use, and its length.
+    fn record(&self, offset: usize) -> Result<(Option<DirEntry<'_>>, usize)> {
+        let corrupt = Error::CorruptDirectory {
+            number: self.number,
+            offset: self.base + offset,
+        };
+        let block_end = (offset / self.block_size + 1) * self.block_size;
+        let header =
+            DirectoryEntry::read_from_prefix(&self.data[offset..block_end]).ok_or(corrupt)?;
+
+        let rec_len = usize::from(header.entry_size.get());
+        let name_len = if self.filetype {
+            usize::from(header.name_length)
+        } else {
+            usize::from(header.name_length) | usize::from(header.type_indicator) << 8
+        };
+        if rec_len < record_len(1)
+            || rec_len % 4 != 0
+            || rec_len < record_len(name_len)
+            || offset + rec_len > block_end
+            || header.inode.get() > self.inodes_count
+        {
+            return Err(corrupt);
+        }
+
+        if header.inode.get() == 0 {
+            return Ok((None, rec_len));
+        }
+        let file_type = if self.filetype {
+            TypeIndicator::try_from(header.type_indicator).unwrap_or(TypeIndicator::Unknown)
+        } else {
+            TypeIndicator::Unknown
+        };
+        let name = &self.data[offset + HEADER_SIZE..offset + HEADER_SIZE + name_len];
+        let entry = DirEntry {
+            inode: header.inode.get(),
+            file_type,
+            name,
+        };
+        Ok((Some(entry), rec_len))
+    }
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Add an entry named `name` for inode number `inode`, of type `file_type`, to the directory
+    /// at inode number `dir`.
+    ///
+    /// In a directory indexed by a hash tree, the entry goes in the leaf for its hash, which is
+    /// split if it is full. Otherwise the entry goes in the first record with room for it, or in
+    /// a new block at the end of the directory; if the directory would grow past one block, it is
+    /// indexed first, unless that is disabled with [`Ext2::set_index_directories`]. A malformed
+    /// index is dropped, and the entry added as if the directory had none.
+    ///
+    /// The directory's modification and change times are updated.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidName`] or [`Error::NameTooLong`] if `name` cannot be stored.
+    /// - [`Error::AlreadyExists`] if the directory already has an entry named `name`.
+    /// - [`Error::NotPermitted`] if the directory is immutable.
+    /// - [`Error::NoSpace`] if a block cannot be allocated, or the hash tree index is full.
+    /// - If the directory cannot be read or written.
+    pub(crate) fn add_entry(
+        &mut self,
+        dir: InodeNumber,
+        name: &[u8],
+        inode: InodeNumber,
+        file_type: FileType,
+    ) -> Result<()> {
+        check_name(name)?;
+        if self.find_entry(dir, name)?.is_some() {
+            return Err(Error::AlreadyExists);
+        }
+        let mut dir_inode = self.inode(dir)?;
+        dir_inode.check_mutable(dir)?;
+        let result = self.insert_entry(dir, &mut dir_inode, name, inode, file_type.indicator());
+        if result.is_ok() {
+            let now = self.now();
+            dir_inode.mtime.set(now);
+            dir_inode.ctime.set(now);
+        }
+        // Blocks allocated before a failure must still be recorded in the inode.
+        self.write_inode(dir, &dir_inode)?;
+        result
+    }
+
+    fn insert_entry(
+        &mut self,
+        dir: InodeNumber,
+        dir_inode: &mut Inode,
+        name: &[u8],
+        inode: InodeNumber,
+        indicator: TypeIndicator,
+    ) -> Result<()> {
+        if self.is_indexed(dir_inode)
+            && self.dx_add_entry(dir, dir_inode, name, inode, indicator)?
+        {
+            return Ok(());
+        }
+        // Adding entries without maintaining the index would leave it stale.
+        dir_inode.set_inode_flags(dir_inode.inode_flags() - InodeFlags::INDEX);
+
+        let listing = self.read_dir(dir)?;
+        if let Some(slot) = listing.find_slot(record_len(name.len()))? {
+            let (block, within) = self.entry_location(dir, dir_inode, slot.offset)?;
+            return self.fill_slot(block, within, slot, name, inode, indicator);
+        }
+
+        if self.should_index(dir_inode) {
+            self.make_indexed(dir, dir_inode)?;
+            if self.dx_add_entry(dir, dir_inode, name, inode, indicator)? {
+                return Ok(());
+            }
+            return Err(Error::CorruptDirectory {
+                number: dir,
+                offset: 0,
+            });
+        }
+
+        let block_size = self.block_size();
+        let (_, block) = self.append_dir_block(dir, dir_inode)?;
+        let mut data = self.entry_bytes(name, inode, indicator, block_size);
+        data.resize(block_size, 0);
+        self.write_block_at(block, 0, &data)
+    }
+
+    /// Write an entry for `name` into `slot`, which is `within` bytes into `block`.
+    pub(crate) fn fill_slot(
+        &mut self,
+        block: u32,
+        within: usize,
+        slot: Slot,
+        name: &[u8],
+        inode: InodeNumber,
+        indicator: TypeIndicator,
+    ) -> Result<()> {
+        if slot.used > 0 {
+            // Shrink the live entry to its name, and put the new one in the slack.
+            let len = u16::try_from(slot.used).expect("records fit in a block");
+            self.write_block_at(block, within + 4, &len.to_le_bytes())?;
+        }
+        let entry = self.entry_bytes(name, inode, indicator, slot.len - slot.used);
+        self.write_block_at(block, within + slot.used, &entry)
+    }
+
+    /// Add a block to the end of the directory at inode number `dir`, returning its logical and
+    /// physical numbers.
+    ///
+    /// The block is not initialized. The size and block pointers of `dir_inode` are updated, and
+    /// the caller must write it back.
+    pub(crate) fn append_dir_block(
+        &mut self,
+        dir: InodeNumber,
+        dir_inode: &mut Inode,
+    ) -> Result<(u32, u32)> {
+        let block_size = self.block_size() as u64;
+        let logical =
+            u32::try_from(dir_inode.size() / block_size).map_err(|_| Error::FileTooLarge)?;
+        let goal = match logical.checked_sub(1) {
+            Some(last) => dir_inode.block_map(self, last)?.map(|block| block + 1),
+            None => None,
+        };
+        let (block, _) = self.map_or_allocate(dir, dir_inode, logical, goal)?;
+        dir_inode.set_size(dir_inode.size() + block_size);
+        Ok((logical, block))
+    }
+
+    /// Remove the entry named `name` from the directory at inode number `dir`, returning the
+    /// inode it pointed to.
+    ///
+    /// The entry's record is merged into the one before it, or marked deleted if it is the first
+    /// in its block. The directory's modification and change times are updated.
+    ///
+    /// # Errors
+    /// - [`Error::NotFound`] if the directory has no entry named `name`.
+    /// - [`Error::NotPermitted`] if the directory is immutable or append-only.
+    /// - If the directory cannot be read or written.
+    pub(crate) fn remove_entry(&mut self, dir: InodeNumber, name: &[u8]) -> Result<InodeNumber> {
+        let mut dir_inode = self.inode(dir)?;
+        let found = self
+            .locate_entry(dir, &dir_inode, name)?
+            .ok_or(Error::NotFound)?;
+        dir_inode.check_removable(dir)?;
+        let (block, within) = self.entry_location(dir, &dir_inode, found.offset)?;
+
+        match found.prev {
+            Some(prev) => {
+                let len =
+                    u16::try_from(found.offset - prev + found.len).expect("records fit in a block");
+                self.write_block_at(block, prev % self.block_size() + 4, &len.to_le_bytes())?;
+            }
+            None => self.write_block_at(block, within, &0u32.to_le_bytes())?,
+        }
+
+        let now = self.now();
+        dir_inode.mtime.set(now);
+        dir_inode.ctime.set(now);
+        self.write_inode(dir, &dir_inode)?;
+        Ok(found.inode)
+    }
+
+    /// Point the existing entry named `name` in the directory at inode number `dir` to inode
+    /// number `inode` of type `file_type`, returning the inode it pointed to before.
+    ///
+    /// # Errors
+    /// - [`Error::NotFound`] if the directory has no entry named `name`.
+    /// - [`Error::NotPermitted`] if the directory is immutable or append-only.
+    /// - If the directory cannot be read or written.
+    pub(crate) fn replace_entry(
+        &mut self,
+        dir: InodeNumber,
+        name: &[u8],
+        inode: InodeNumber,
+        file_type: FileType,
+    ) -> Result<InodeNumber> {
+        let mut dir_inode = self.inode(dir)?;
+        let found = self
+            .locate_entry(dir, &dir_inode, name)?
+            .ok_or(Error::NotFound)?;
+        dir_inode.check_removable(dir)?;
+        let (block, within) = self.entry_location(dir, &dir_inode, found.offset)?;
+
+        let entry = self.entry_bytes(name, inode, file_type.indicator(), found.len);
+        self.write_block_at(block, within, &entry)?;
+
+        let now = self.now();
+        dir_inode.mtime.set(now);
+        dir_inode.ctime.set(now);
+        self.write_inode(dir, &dir_inode)?;
+        Ok(found.inode)
+    }
+
+    /// The block holding the record at `offset` in the directory at inode number `dir`, and the
+    /// record's offset in it.
+    pub(crate) fn entry_location(
+        &self,
+        dir: InodeNumber,
+        dir_inode: &Inode,
+        offset: usize,
+    ) -> Result<(u32, usize)> {
+        let block_size = self.block_size();
+        let logical = u32::try_from(offset / block_size).map_err(|_| Error::FileTooLarge)?;
+        let block = dir_inode
+            .block_map(self, logical)?
+            .ok_or(Error::CorruptDirectory {
+                number: dir,
+                offset,
+            })?;
+        Ok((block, offset % block_size))
+    }
+
+    /// The bytes of a directory entry for `name`, without the padding up to `len`.
+    pub(crate) fn entry_bytes(
+        &self,
+        name: &[u8],
+        inode: InodeNumber,
+        indicator: TypeIndicator,
+        len: usize,
+    ) -> Vec<u8> {
+        let filetype = self
+            .superblock()
+            .incompat_features()
+            .contains(IncompatFeatures::FILETYPE);
+        let mut header = DirectoryEntry::new_zeroed();
+        header.inode.set(inode);
+        header
+            .entry_size
+            .set(u16::try_from(len).expect("records fit in a block"));
+        header.name_length = name.len() as u8;
+        if filetype {
+            header.type_indicator = indicator as u8;
+        }
+
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend_from_slice(name);
+        bytes
+    }
+}
+
+impl<'a> IntoIterator for &'a Dir {
+    type Item = Result<DirEntry<'a>>;
+    type IntoIter = DirIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a directory, created by [`Dir::iter`].
+///
+/// The iterator stops after the first malformed record.
+#[derive(Debug, Clone)]
+pub struct DirIter<'a> {
+    dir: &'a Dir,
+    offset: usize,
+}
+
+impl<'a> Iterator for DirIter<'a> {
+    type Item = Result<DirEntry<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset < self.dir.data.len() {
+            match self.dir.record(self.offset) {
+                Ok((entry, rec_len)) => {
+                    self.offset += rec_len;
+                    if entry.is_some() {
+                        return entry.map(Ok);
+                    }
+                }
+                Err(err) => {
+                    self.offset = self.dir.data.len();
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn lists_root_directory() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let dir = fs.read_dir(2).unwrap();
+        let entries = dir.iter().collect::<Result<Vec<_>>>().unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.name).collect();
+
+        assert_eq!(
+            names,
+            [
+                &b"."[..],
+                b"..",
+                b"lost+found",
+                b"test_directory",
+                b"hello.txt"
+            ]
+        );
+        assert_eq!(entries[4].inode, 14);
+        assert_eq!(entries[4].file_type, TypeIndicator::Regular);
+        assert_eq!(entries[3].file_type, TypeIndicator::Directory);
+    }
+
+    #[test]
+    fn finds_entry_by_name() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let dir = fs.read_dir(1281).unwrap();
+
+        assert_eq!(
+            dir.find(b"file_in_folder.txt").unwrap().unwrap().inode,
+            1284
+        );
+        assert_eq!(dir.find(b"missing").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_non_directories() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.read_dir(14).unwrap_err(), Error::NotADirectory(14));
+    }
+
+    #[test]
+    fn rejects_malformed_record_length() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let block = fs.inode(1281).unwrap().direct_pointer[0].get();
+        fs.write_block_at(block, 4, &6u16.to_le_bytes()).unwrap();
+
+        let dir = fs.read_dir(1281).unwrap();
+        let mut iter = dir.iter();
+        assert_eq!(
+            iter.next(),
+            Some(Err(Error::CorruptDirectory {
+                number: 1281,
+                offset: 0
+            }))
+        );
+        assert_eq!(iter.next(), None);
+    }
+}
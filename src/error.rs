@@ -0,0 +1,194 @@
+//! Errors from operating on a filesystem.
+
+use core::fmt;
+
+use crate::device::DeviceError;
+use crate::features::IncompatFeatures;
+use crate::parse::ParseError;
+use crate::schema::InodeNumber;
+
+/// The reasons an operation on a filesystem can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The superblock was rejected.
+    Parse(ParseError),
+    /// The underlying device failed.
+    Device(DeviceError),
+    /// The filesystem uses incompatible features this crate does not support.
+    UnsupportedFeatures(IncompatFeatures),
+    /// The filesystem was mounted read-only.
+    ReadOnly,
+    /// The superblock describes an impossible arrangement of block groups.
+    BadGeometry,
+    /// A block group descriptor points outside of the filesystem.
+    BadGroupDescriptor {
+        /// The index of the offending group.
+        group: u32,
+    },
+    /// The inode number is 0 or past the end of the inode tables.
+    InvalidInode(InodeNumber),
+    /// The contents of the inode are inconsistent with the filesystem.
+    CorruptInode(InodeNumber),
+    /// A block pointer is past the end of the filesystem.
+    InvalidBlock(u32),
+    /// The inode is not a regular file.
+    NotAFile(InodeNumber),
+    /// The offset is past the largest file the block pointers can describe.
+    FileTooLarge,
+    /// The inode is not a directory.
+    NotADirectory(InodeNumber),
+    /// The inode is a directory, which the operation does not apply to.
+    IsADirectory(InodeNumber),
+    /// The directory has entries other than `.` and `..`.
+    NotEmpty(InodeNumber),
+    /// The inode already has the most hard links it can have.
+    TooManyLinks(InodeNumber),
+    /// The inode is immutable or append-only, which forbids the operation.
+    NotPermitted(InodeNumber),
+    /// The permissions of the inode do not allow the requested access.
+    AccessDenied(InodeNumber),
+    /// A directory cannot be moved into itself or one of its subdirectories, and `.` and `..`
+    /// cannot be moved or removed.
+    InvalidRename,
+    /// A directory contains a malformed record.
+    CorruptDirectory {
+        /// The directory's inode number.
+        number: InodeNumber,
+        /// The offset of the malformed record in the directory.
+        offset: usize,
+    },
+    /// No entry with the requested name exists.
+    NotFound,
+    /// A path component is longer than a directory entry can hold.
+    NameTooLong,
+    /// A name is empty, or contains a slash or NUL.
+    InvalidName,
+    /// An entry with the requested name already exists.
+    AlreadyExists,
+    /// The inode has no extended attribute with the requested name.
+    NoAttribute,
+    /// An extended attribute name is not in a namespace this crate supports.
+    UnsupportedNamespace,
+    /// An access control list is malformed.
+    InvalidAcl,
+    /// A label or path is too long for its superblock field, or contains a NUL.
+    InvalidLabel,
+    /// Resolving a path followed too many symbolic links.
+    TooManySymlinks,
+    /// The inode is not a symbolic link.
+    NotASymlink(InodeNumber),
+    /// No free blocks or inodes are available.
+    NoSpace,
+    /// A usage bitmap disagrees with the block group's free counts.
+    CorruptBitmap {
+        /// The index of the offending group.
+        group: u32,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(err) => write!(f, "invalid superblock: {err}"),
+            Self::Device(err) => write!(f, "{err}"),
+            Self::UnsupportedFeatures(features) => {
+                write!(
+                    f,
+                    "unsupported incompatible features {:#x}",
+                    features.bits()
+                )
+            }
+            Self::ReadOnly => write!(f, "filesystem is read-only"),
+            Self::BadGeometry => write!(f, "inconsistent block group geometry"),
+            Self::BadGroupDescriptor { group } => {
+                write!(f, "invalid descriptor for block group {group}")
+            }
+            Self::InvalidInode(number) => write!(f, "invalid inode number {number}"),
+            Self::CorruptInode(number) => write!(f, "inode {number} is corrupt"),
+            Self::InvalidBlock(block) => write!(f, "invalid block number {block}"),
+            Self::NotAFile(number) => write!(f, "inode {number} is not a regular file"),
+            Self::FileTooLarge => write!(f, "file too large"),
+            Self::NotADirectory(number) => write!(f, "inode {number} is not a directory"),
+            Self::IsADirectory(number) => write!(f, "inode {number} is a directory"),
+            Self::NotEmpty(number) => write!(f, "directory {number} is not empty"),
+            Self::TooManyLinks(number) => write!(f, "inode {number} has too many links"),
+            Self::NotPermitted(number) => write!(f, "operation not permitted on inode {number}"),
+            Self::AccessDenied(number) => write!(f, "permission denied on inode {number}"),
+            Self::InvalidRename => write!(f, "invalid move or removal of a directory entry"),
+            Self::CorruptDirectory { number, offset } => {
+                write!(f, "directory {number} is corrupt at offset {offset}")
+            }
+            Self::NotFound => write!(f, "no such file or directory"),
+            Self::NameTooLong => write!(f, "file name too long"),
+            Self::InvalidName => write!(f, "invalid file name"),
+            Self::AlreadyExists => write!(f, "file exists"),
+            Self::NoAttribute => write!(f, "no such attribute"),
+            Self::UnsupportedNamespace => write!(f, "unsupported attribute namespace"),
+            Self::InvalidAcl => write!(f, "invalid access control list"),
+            Self::InvalidLabel => write!(f, "label too long or contains a NUL"),
+            Self::TooManySymlinks => write!(f, "too many levels of symbolic links"),
+            Self::NotASymlink(number) => write!(f, "inode {number} is not a symbolic link"),
+            Self::NoSpace => write!(f, "no space left on device"),
+            Self::CorruptBitmap { group } => {
+                write!(f, "usage bitmap of block group {group} is corrupt")
+            }
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+impl From<DeviceError> for Error {
+    fn from(err: DeviceError) -> Self {
+        Self::Device(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DeviceError {}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match err {
+            Error::Device(DeviceError::Std(kind)) => kind,
+            Error::InvalidInode(_) => ErrorKind::InvalidInput,
+            Error::UnsupportedFeatures(_) => ErrorKind::Unsupported,
+            Error::ReadOnly => ErrorKind::ReadOnlyFilesystem,
+            Error::NotFound => ErrorKind::NotFound,
+            Error::NotADirectory(_) => ErrorKind::NotADirectory,
+            Error::FileTooLarge => ErrorKind::FileTooLarge,
+            Error::InvalidLabel => ErrorKind::InvalidInput,
+            Error::NoSpace => ErrorKind::StorageFull,
+            Error::InvalidName => ErrorKind::InvalidInput,
+            Error::AlreadyExists => ErrorKind::AlreadyExists,
+            Error::IsADirectory(_) => ErrorKind::IsADirectory,
+            Error::NotEmpty(_) => ErrorKind::DirectoryNotEmpty,
+            Error::TooManyLinks(_) => ErrorKind::TooManyLinks,
+            Error::InvalidRename => ErrorKind::InvalidInput,
+            Error::NotPermitted(_) => ErrorKind::PermissionDenied,
+            Error::AccessDenied(_) => ErrorKind::PermissionDenied,
+            Error::NoAttribute => ErrorKind::NotFound,
+            Error::UnsupportedNamespace => ErrorKind::Unsupported,
+            Error::InvalidAcl => ErrorKind::InvalidInput,
+            _ => ErrorKind::InvalidData,
+        };
+        Self::new(kind, err)
+    }
+}
+
+/// A `Result` whose error is an ext2 [`Error`].
+pub type Result<T, E = Error> = core::result::Result<T, E>;
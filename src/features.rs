@@ -0,0 +1,250 @@
+//! Decode the superblock's feature flags and decide how a filesystem may be mounted.
+
+use bitflags::bitflags;
+
+use crate::error::{Error, Result};
+use crate::schema::{Superblock, EXT2_GOOD_OLD_REV};
+
+bitflags! {
+    /// Compatible features: an implementation which does not understand them may still read and
+    /// write the filesystem.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct CompatFeatures: u32 {
+        /// Blocks are preallocated for new directories.
+        const DIR_PREALLOC = 0x0001;
+        /// AFS server inodes exist.
+        const IMAGIC_INODES = 0x0002;
+        /// The filesystem has an ext3 journal.
+        const HAS_JOURNAL = 0x0004;
+        /// Inodes have extended attributes.
+        const EXT_ATTR = 0x0008;
+        /// Blocks are reserved so the filesystem can be resized.
+        const RESIZE_INODE = 0x0010;
+        /// Directories use hashed b-tree indexes.
+        const DIR_INDEX = 0x0020;
+    }
+}
+
+bitflags! {
+    /// Incompatible features: an implementation which does not understand them must not mount the
+    /// filesystem.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct IncompatFeatures: u32 {
+        /// Files are compressed.
+        const COMPRESSION = 0x0001;
+        /// Directory entries record the type of the inode they point to.
+        const FILETYPE = 0x0002;
+        /// The journal needs to be replayed.
+        const RECOVER = 0x0004;
+        /// The filesystem is an external journal device.
+        const JOURNAL_DEV = 0x0008;
+        /// Block group descriptors are spread across meta block groups.
+        const META_BG = 0x0010;
+        /// Files use extent trees instead of block pointers.
+        const EXTENTS = 0x0040;
+        /// Block numbers are 64 bits wide.
+        const BIT64 = 0x0080;
+        /// Multiple mount protection is enabled.
+        const MMP = 0x0100;
+        /// Block group metadata is packed into flexible groups.
+        const FLEX_BG = 0x0200;
+        /// Large extended attribute values are stored in inodes.
+        const EA_INODE = 0x0400;
+        /// Directory entries hold extra data after the name.
+        const DIRDATA = 0x1000;
+        /// The metadata checksum seed is stored in the superblock.
+        const CSUM_SEED = 0x2000;
+        /// Directories may be larger than 2 GiB or have a three level index.
+        const LARGEDIR = 0x4000;
+        /// Small files are stored inside their inodes.
+        const INLINE_DATA = 0x8000;
+        /// Some files are encrypted.
+        const ENCRYPT = 0x1_0000;
+        /// Some directories have case-insensitive names.
+        const CASEFOLD = 0x2_0000;
+    }
+}
+
+bitflags! {
+    /// Read-only compatible features: an implementation which does not understand them may read
+    /// the filesystem, but must not write to it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct RoCompatFeatures: u32 {
+        /// Only some block groups hold backups of the superblock and descriptors.
+        const SPARSE_SUPER = 0x0001;
+        /// Files may be larger than 2 GiB.
+        const LARGE_FILE = 0x0002;
+        /// Directories use b-trees (never implemented).
+        const BTREE_DIR = 0x0004;
+        /// Files may be larger than 2 TiB.
+        const HUGE_FILE = 0x0008;
+        /// Block group descriptors have checksums.
+        const GDT_CSUM = 0x0010;
+        /// Directories may have more than 65000 subdirectories.
+        const DIR_NLINK = 0x0020;
+        /// Inodes reserve space for the extra fields.
+        const EXTRA_ISIZE = 0x0040;
+        /// Quotas are stored in hidden inodes.
+        const QUOTA = 0x0100;
+        /// Blocks are allocated in clusters.
+        const BIGALLOC = 0x0200;
+        /// Metadata has checksums.
+        const METADATA_CSUM = 0x0400;
+        /// The filesystem must never be mounted writable.
+        const READONLY = 0x1000;
+        /// Project quotas are tracked.
+        const PROJECT = 0x2000;
+        /// Some files have verity metadata.
+        const VERITY = 0x8000;
+    }
+}
+
+/// The incompatible features this crate understands.
+pub const SUPPORTED_INCOMPAT: IncompatFeatures = IncompatFeatures::FILETYPE;
+
+/// The read-only compatible features this crate can write without breaking.
+pub const SUPPORTED_RO_COMPAT: RoCompatFeatures = RoCompatFeatures::SPARSE_SUPER
+    .union(RoCompatFeatures::LARGE_FILE)
+    .union(RoCompatFeatures::BTREE_DIR);
+
+/// How a filesystem may be accessed, decided from its features by [`MountMode::for_superblock`]
+/// and from whether its device is read-only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MountMode {
+    /// The filesystem may be read and written.
+    ReadWrite,
+    /// The filesystem may only be read.
+    ReadOnly,
+}
+
+impl Superblock {
+    /// The compatible features in use. Revision 0 filesystems have none.
+    #[must_use]
+    pub fn compat_features(&self) -> CompatFeatures {
+        if self.rev_major.get() == EXT2_GOOD_OLD_REV {
+            return CompatFeatures::empty();
+        }
+        CompatFeatures::from_bits_retain(self.features_opt.get())
+    }
+
+    /// The incompatible features in use. Revision 0 filesystems have none.
+    #[must_use]
+    pub fn incompat_features(&self) -> IncompatFeatures {
+        if self.rev_major.get() == EXT2_GOOD_OLD_REV {
+            return IncompatFeatures::empty();
+        }
+        IncompatFeatures::from_bits_retain(self.features_req.get())
+    }
+
+    /// The read-only compatible features in use. Revision 0 filesystems have none.
+    #[must_use]
+    pub fn ro_compat_features(&self) -> RoCompatFeatures {
+        if self.rev_major.get() == EXT2_GOOD_OLD_REV {
+            return RoCompatFeatures::empty();
+        }
+        RoCompatFeatures::from_bits_retain(self.features_ronly.get())
+    }
+}
+
+impl MountMode {
+    /// Decide how the filesystem described by `superblock` may be accessed.
+    ///
+    /// Filesystems using read-only compatible features this crate does not support, or marked
+    /// [`RoCompatFeatures::READONLY`], are mounted read-only.
+    ///
+    /// # Errors
+    /// [`Error::UnsupportedFeatures`] if the filesystem uses incompatible features this crate does
+    /// not support.
+    pub fn for_superblock(superblock: &Superblock) -> Result<Self> {
+        let unsupported = superblock
+            .incompat_features()
+            .difference(SUPPORTED_INCOMPAT);
+        if !unsupported.is_empty() {
+            return Err(Error::UnsupportedFeatures(unsupported));
+        }
+        if superblock
+            .ro_compat_features()
+            .difference(SUPPORTED_RO_COMPAT)
+            .is_empty()
+        {
+            Ok(Self::ReadWrite)
+        } else {
+            Ok(Self::ReadOnly)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn decodes_image_features() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let sb = fs.superblock();
+
+        assert_eq!(
+            sb.compat_features(),
+            CompatFeatures::EXT_ATTR | CompatFeatures::RESIZE_INODE | CompatFeatures::DIR_INDEX
+        );
+        assert_eq!(sb.incompat_features(), IncompatFeatures::FILETYPE);
+        assert_eq!(
+            sb.ro_compat_features(),
+            RoCompatFeatures::SPARSE_SUPER | RoCompatFeatures::LARGE_FILE
+        );
+        let mut image = IMAGE.to_vec();
+        let fs = crate::Ext2::new(&mut image[..]).unwrap();
+        assert_eq!(fs.mount_mode(), MountMode::ReadWrite);
+    }
+
+    #[test]
+    fn refuses_unknown_incompat_features() {
+        let mut sb = Filesystem::new(IMAGE).unwrap().superblock().clone();
+        sb.features_req
+            .set(sb.features_req.get() | IncompatFeatures::EXTENTS.bits() | 0x8000_0000);
+
+        assert_eq!(
+            MountMode::for_superblock(&sb),
+            Err(Error::UnsupportedFeatures(
+                IncompatFeatures::EXTENTS | IncompatFeatures::from_bits_retain(0x8000_0000)
+            ))
+        );
+    }
+
+    #[test]
+    fn forces_read_only_on_unknown_ro_compat_features() {
+        let mut sb = Filesystem::new(IMAGE).unwrap().superblock().clone();
+        sb.features_opt.set(sb.features_opt.get() | 0x8000_0000);
+        assert_eq!(MountMode::for_superblock(&sb), Ok(MountMode::ReadWrite));
+
+        sb.features_ronly
+            .set(sb.features_ronly.get() | RoCompatFeatures::METADATA_CSUM.bits());
+        assert_eq!(MountMode::for_superblock(&sb), Ok(MountMode::ReadOnly));
+    }
+
+    #[test]
+    fn refuses_writes_when_read_only() {
+        let mut image = IMAGE.to_vec();
+        let flags = u32::from_le_bytes(image[1024 + 100..1024 + 104].try_into().unwrap());
+        let flags = flags | RoCompatFeatures::READONLY.bits();
+        image[1024 + 100..1024 + 104].copy_from_slice(&flags.to_le_bytes());
+        let mut fs = crate::Ext2::new(&mut image[..]).unwrap();
+
+        assert_eq!(fs.mount_mode(), MountMode::ReadOnly);
+        assert_eq!(fs.write_block_at(517, 0, b"x"), Err(Error::ReadOnly));
+    }
+
+    #[test]
+    fn opens_read_only_devices_read_only() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.mount_mode(), MountMode::ReadOnly);
+        assert_eq!(
+            fs.check_access(14, 0, &[0], crate::Access::WRITE),
+            Err(Error::ReadOnly)
+        );
+    }
+}
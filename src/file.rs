@@ -0,0 +1,226 @@
+//! Read the contents of regular files.
+
+use crate::blocks::IndirectCache;
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::schema::{Inode, InodeNumber};
+
+/// An open regular file, created by [`Ext2::open`].
+#[derive(Debug)]
+pub struct File<'a, D> {
+    fs: &'a Ext2<D>,
+    number: InodeNumber,
+    inode: Inode,
+    pos: u64,
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Open inode number `number`, which must be a regular file, for reading.
+    ///
+    /// # Errors
+    /// If the inode cannot be read or is not a regular file.
+    pub fn open(&self, number: InodeNumber) -> Result<File<'_, D>> {
+        let inode = self.inode(number)?;
+        if !inode.is_file() {
+            return Err(Error::NotAFile(number));
+        }
+        Ok(File {
+            fs: self,
+            number,
+            inode,
+            pos: 0,
+        })
+    }
+
+    /// Read the data of `inode` starting at `offset` into `buf`, returning how many bytes were
+    /// read.
+    pub(crate) fn read_data(&self, inode: &Inode, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let remaining = inode.size().saturating_sub(offset);
+        let len = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let block_size = self.block_size() as u64;
+        let mut cache = IndirectCache::default();
+
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let within = (pos % block_size) as usize;
+            let chunk = (len - done).min(block_size as usize - within);
+            let dest = &mut buf[done..done + chunk];
+
+            let logical = u32::try_from(pos / block_size).map_err(|_| Error::FileTooLarge)?;
+            match inode.block_map_cached(self, logical, &mut cache)? {
+                Some(block) => self.read_block_at(block, within, dest)?,
+                None => dest.fill(0),
+            }
+            done += chunk;
+        }
+        Ok(len)
+    }
+}
+
+impl<D: BlockDevice> File<'_, D> {
+    /// The number of the file's inode.
+    #[must_use]
+    pub const fn number(&self) -> InodeNumber {
+        self.number
+    }
+
+    /// The file's inode, as it was when the file was opened.
+    #[must_use]
+    pub const fn inode(&self) -> &Inode {
+        &self.inode
+    }
+
+    /// The length of the file, in bytes.
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.inode.size()
+    }
+
+    /// Whether the file is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The offset the next sequential read or seek is relative to.
+    #[must_use]
+    pub const fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Set the offset the next sequential read or seek is relative to.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Read bytes starting at `offset` into `buf`, returning how many were read.
+    ///
+    /// Fewer than `buf.len()` bytes are read only if the end of the file is reached. Holes in the
+    /// file read as zeros.
+    ///
+    /// # Errors
+    /// If the file's blocks cannot be mapped or read.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.fs.read_data(&self.inode, offset, buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D: BlockDevice> std::io::Read for File<'_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.read_at(self.pos, buf)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D: BlockDevice> std::io::Seek for File<'_, D> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.pos = seek_position(pos, self.pos, self.len())?;
+        Ok(self.pos)
+    }
+}
+
+/// The position reached by seeking to `pos` from `current` in a file of length `len`.
+#[cfg(feature = "std")]
+pub(crate) fn seek_position(
+    pos: std::io::SeekFrom,
+    current: u64,
+    len: u64,
+) -> std::io::Result<u64> {
+    let (base, delta) = match pos {
+        std::io::SeekFrom::Start(offset) => (0, i128::from(offset)),
+        std::io::SeekFrom::End(delta) => (len, i128::from(delta)),
+        std::io::SeekFrom::Current(delta) => (current, i128::from(delta)),
+    };
+    u64::try_from(i128::from(base) + delta).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "seek to a negative position",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn reads_whole_file() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let file = fs.open(14).unwrap();
+        let mut buf = [0; 64];
+
+        assert_eq!(file.len(), 19);
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 19);
+        assert_eq!(&buf[..19], b"Hello, ext2 world!\n");
+    }
+
+    #[test]
+    fn reads_at_offset_and_stops_at_eof() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let file = fs.open(14).unwrap();
+        let mut buf = [0; 5];
+
+        assert_eq!(file.read_at(7, &mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"ext2 ");
+        assert_eq!(file.read_at(17, &mut buf).unwrap(), 2);
+        assert_eq!(file.read_at(100, &mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_holes_as_zeros() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut inode = fs.inode(14).unwrap();
+        inode.size_low.set(2048 + 19);
+        inode.direct_pointer[2] = inode.direct_pointer[0];
+        inode.direct_pointer[0].set(0);
+        fs.write_inode(14, &inode).unwrap();
+
+        let file = fs.open(14).unwrap();
+        let mut buf = [0xff; 2048 + 19];
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), buf.len());
+        assert!(buf[..2048].iter().all(|&b| b == 0));
+        assert_eq!(&buf[2048..], b"Hello, ext2 world!\n");
+    }
+
+    #[test]
+    fn combines_high_size_for_regular_files() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let mut inode = fs.inode(14).unwrap();
+        inode.size_high.set(1);
+
+        assert_eq!(inode.size(), (1 << 32) + 19);
+    }
+
+    #[test]
+    fn refuses_to_open_directories() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.open(2).unwrap_err(), Error::NotAFile(2));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn implements_read_and_seek() {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let mut file = fs.open(14).unwrap();
+        let mut contents = std::string::String::new();
+
+        file.seek(SeekFrom::End(-7)).unwrap();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "world!\n");
+        assert!(file.seek(SeekFrom::Current(-100)).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//! The filesystem handle.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::features::MountMode;
+use crate::schema::{
+    BlockGroupDescriptor, Superblock, EXT2_END_OF_SUPERBLOCK, EXT2_START_OF_SUPERBLOCK,
+};
+
+/// An open ext2 filesystem on a [`BlockDevice`].
+#[derive(Debug)]
+pub struct Ext2<D> {
+    device: D,
+    superblock: Superblock,
+    groups: Vec<BlockGroupDescriptor>,
+    mount_mode: MountMode,
+    credentials: Credentials,
+    clock: Clock,
+    index_directories: bool,
+}
+
+/// A source of the current time, in seconds since the Unix epoch, used to timestamp changes.
+pub type Clock = fn() -> u32;
+
+/// The clock used unless another is set with [`Ext2::set_clock`]: the system time with the `std`
+/// feature, and the epoch without it.
+fn default_clock() -> u32 {
+    #[cfg(feature = "std")]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() as u32)
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        0
+    }
+}
+
+/// The user and group on whose behalf a filesystem is modified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Credentials {
+    /// User ID
+    pub uid: u32,
+    /// Group ID
+    pub gid: u32,
+}
+
+impl Credentials {
+    /// The superuser.
+    pub const ROOT: Self = Self { uid: 0, gid: 0 };
+
+    /// Whether these credentials may allocate the blocks reserved by `superblock`.
+    ///
+    /// Like Linux, the reserved group only counts if it was set to something other than the
+    /// default of 0, so that being in group 0 is not enough.
+    #[must_use]
+    pub fn may_use_reserved(&self, superblock: &Superblock) -> bool {
+        let block_gid = u32::from(superblock.block_gid.get());
+        self.uid == 0
+            || self.uid == u32::from(superblock.block_uid.get())
+            || (block_gid != 0 && self.gid == block_gid)
+    }
+}
+
+impl Default for Credentials {
+    fn default() -> Self {
+        Self::ROOT
+    }
+}
+
+/// An ext2 filesystem stored in a byte slice, which is opened read-only.
+pub type Filesystem<'a> = Ext2<&'a [u8]>;
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Open the filesystem on `device`, loading its superblock and block group descriptors.
+    ///
+    /// The filesystem is opened read-only if `device` is read-only, or the filesystem uses
+    /// features this crate cannot write; see [`MountMode::for_superblock`].
+    ///
+    /// # Errors
+    /// If the device cannot be read, does not contain a consistent ext2 filesystem, or the
+    /// filesystem uses features this crate does not support.
+    pub fn new(device: D) -> Result<Self> {
+        let mut bytes = [0; EXT2_END_OF_SUPERBLOCK - EXT2_START_OF_SUPERBLOCK];
+        device.read_at(EXT2_START_OF_SUPERBLOCK as u64, &mut bytes)?;
+        let superblock = Superblock::parse(&bytes)?;
+        let mut mount_mode = MountMode::for_superblock(&superblock)?;
+        if device.is_read_only() {
+            mount_mode = MountMode::ReadOnly;
+        }
+
+        let mut fs = Self {
+            device,
+            superblock,
+            groups: Vec::new(),
+            mount_mode,
+            credentials: Credentials::ROOT,
+            clock: default_clock,
+            index_directories: true,
+        };
+        fs.check_geometry()?;
+        fs.load_groups()?;
+        Ok(fs)
+    }
+
+    /// The filesystem's superblock.
+    #[must_use]
+    pub const fn superblock(&self) -> &Superblock {
+        &self.superblock
+    }
+
+    /// The block group descriptor table.
+    #[must_use]
+    pub fn groups(&self) -> &[BlockGroupDescriptor] {
+        &self.groups
+    }
+
+    /// The descriptor of block group `group`, if it exists.
+    #[must_use]
+    pub fn group(&self, group: u32) -> Option<&BlockGroupDescriptor> {
+        self.groups.get(group as usize)
+    }
+
+    /// The number of block groups.
+    #[must_use]
+    pub fn group_count(&self) -> u32 {
+        let sb = &self.superblock;
+        (sb.blocks_count.get() - sb.first_data_block.get()).div_ceil(sb.blocks_per_group.get())
+    }
+
+    /// The size of a block, in bytes.
+    #[must_use]
+    pub fn block_size(&self) -> usize {
+        self.superblock.block_size()
+    }
+
+    /// Whether the filesystem may be written to.
+    #[must_use]
+    pub const fn mount_mode(&self) -> MountMode {
+        self.mount_mode
+    }
+
+    /// The credentials modifications are made with, which are [`Credentials::ROOT`] unless
+    /// changed with [`Ext2::set_credentials`].
+    #[must_use]
+    pub const fn credentials(&self) -> Credentials {
+        self.credentials
+    }
+
+    /// Set the credentials modifications are made with.
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = credentials;
+    }
+
+    /// Set the clock used to timestamp changes.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Whether directories are indexed by hash trees when they outgrow one block, which they are
+    /// unless disabled with [`Ext2::set_index_directories`] or the filesystem lacks the
+    /// `dir_index` feature.
+    #[must_use]
+    pub const fn index_directories(&self) -> bool {
+        self.index_directories
+    }
+
+    /// Set whether directories are indexed by hash trees when they outgrow one block.
+    ///
+    /// Directories which are already indexed stay indexed either way.
+    pub fn set_index_directories(&mut self, enabled: bool) {
+        self.index_directories = enabled;
+    }
+
+    /// The current time, according to the filesystem's clock.
+    pub(crate) fn now(&self) -> u32 {
+        (self.clock)()
+    }
+
+    /// The underlying device.
+    #[must_use]
+    pub const fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Close the filesystem, returning the underlying device.
+    #[must_use]
+    pub fn into_device(self) -> D {
+        self.device
+    }
+
+    /// Read bytes from block `block`, starting `offset` bytes into it.
+    pub(crate) fn read_block_at(&self, block: u32, offset: usize, buf: &mut [u8]) -> Result<()> {
+        let addr = u64::from(block) * self.block_size() as u64 + offset as u64;
+        self.device.read_at(addr, buf)?;
+        Ok(())
+    }
+
+    /// Write bytes to block `block`, starting `offset` bytes into it.
+    pub(crate) fn write_block_at(&mut self, block: u32, offset: usize, buf: &[u8]) -> Result<()> {
+        if self.mount_mode == MountMode::ReadOnly {
+            return Err(Error::ReadOnly);
+        }
+        let addr = u64::from(block) * self.block_size() as u64 + offset as u64;
+        self.device.write_at(addr, buf)?;
+        Ok(())
+    }
+
+    /// Replace the superblock, writing it to the device.
+    pub(crate) fn write_superblock(&mut self, superblock: Superblock) -> Result<()> {
+        if self.mount_mode == MountMode::ReadOnly {
+            return Err(Error::ReadOnly);
+        }
+        self.device
+            .write_at(EXT2_START_OF_SUPERBLOCK as u64, superblock.as_bytes())?;
+        self.superblock = superblock;
+        Ok(())
+    }
+
+    /// Replace the descriptor of block group `group`, writing it to the descriptor table.
+    pub(crate) fn write_group(&mut self, group: u32, desc: BlockGroupDescriptor) -> Result<()> {
+        let offset = group as usize * size_of::<BlockGroupDescriptor>();
+        let block = self.superblock.first_data_block.get() + 1;
+        let block = block + u32::try_from(offset / self.block_size()).expect("groups fit");
+        self.write_block_at(block, offset % self.block_size(), desc.as_bytes())?;
+        self.groups[group as usize] = desc;
+        Ok(())
+    }
+
+    fn check_geometry(&self) -> Result<()> {
+        let sb = &self.superblock;
+        let bits_per_block = 8 * self.block_size() as u32;
+        let first_data_block = u32::from(self.block_size() == 1024);
+
+        if sb.blocks_per_group.get() == 0
+            || sb.inodes_per_group.get() == 0
+            || sb.blocks_per_group.get() > bits_per_block
+            || sb.inodes_per_group.get() > bits_per_block
+            || sb.first_data_block.get() != first_data_block
+            || sb.blocks_count.get() <= sb.first_data_block.get()
+        {
+            return Err(Error::BadGeometry);
+        }
+        if sb.inodes_count.get().div_ceil(sb.inodes_per_group.get()) != self.group_count() {
+            return Err(Error::BadGeometry);
+        }
+        Ok(())
+    }
+
+    fn load_groups(&mut self) -> Result<()> {
+        let count = self.group_count() as usize;
+        let mut bytes = vec![0; count * size_of::<BlockGroupDescriptor>()];
+        self.read_block_at(self.superblock.first_data_block.get() + 1, 0, &mut bytes)?;
+
+        self.groups = bytes
+            .chunks_exact(size_of::<BlockGroupDescriptor>())
+            .map(|chunk| BlockGroupDescriptor::read_from(chunk).expect("chunk is exact"))
+            .collect();
+
+        for (group, desc) in (0..).zip(&self.groups) {
+            let in_bounds = |block: u32| {
+                (self.superblock.first_data_block.get()..self.superblock.blocks_count.get())
+                    .contains(&block)
+            };
+            if !in_bounds(desc.block_usage_addr.get())
+                || !in_bounds(desc.inode_usage_addr.get())
+                || !in_bounds(desc.inode_table_block.get())
+                || u32::from(desc.free_inodes_count.get()) > self.superblock.inodes_per_group.get()
+                || u32::from(desc.free_blocks_count.get()) > self.superblock.blocks_per_group.get()
+            {
+                return Err(Error::BadGroupDescriptor { group });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ParseError;
+    use crate::schema::EXT2_MAGIC;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn filesystem_reads_superblock() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.superblock().magic.get(), EXT2_MAGIC);
+        assert_eq!(fs.superblock().inodes_count.get(), 2560);
+    }
+
+    #[test]
+    fn filesystem_rejects_truncated_image() {
+        assert_eq!(
+            Filesystem::new(&IMAGE[..1100]).unwrap_err(),
+            Error::Device(crate::DeviceError::OutOfBounds {
+                offset: 1024,
+                len: 1024
+            })
+        );
+    }
+
+    #[test]
+    fn filesystem_rejects_bad_magic() {
+        let mut image = IMAGE[..4096].to_vec();
+        image[1024 + 56] = 0;
+
+        assert_eq!(
+            Filesystem::new(&image).unwrap_err(),
+            Error::Parse(ParseError::BadMagic(0xef00))
+        );
+    }
+
+    #[test]
+    fn filesystem_loads_group_descriptors() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.block_size(), 1024);
+        assert_eq!(fs.group_count(), 2);
+        assert_eq!(fs.groups().len(), 2);
+        let free: u32 = fs
+            .groups()
+            .iter()
+            .map(|g| u32::from(g.free_blocks_count))
+            .sum();
+        assert_eq!(free, fs.superblock().free_blocks_count.get());
+    }
+}
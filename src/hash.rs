@@ -0,0 +1,297 @@
+//! The hash functions used to index directories.
+
+/// The hash returned for names which would hash to the end-of-directory marker.
+const EOF_HASH: u32 = (0x7fff_ffff - 1) << 1;
+
+/// The initial state of the half MD4 and TEA hashes, used when the seed is zero.
+const DEFAULT_SEED: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+/// A hash function for the names in indexed directories.
+///
+/// The unsigned variants treat the bytes of a name as unsigned, and differ from the signed ones
+/// only for names with bytes of 0x80 and above. Which is used is recorded in the superblock's
+/// `flags`, since Linux used to hash with the platform's `char` signedness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashVersion {
+    /// The original hash, from before the others were added
+    Legacy,
+    /// Half of the MD4 transform
+    HalfMd4,
+    /// The Tiny Encryption Algorithm
+    Tea,
+    /// [`HashVersion::Legacy`] over unsigned bytes
+    LegacyUnsigned,
+    /// [`HashVersion::HalfMd4`] over unsigned bytes
+    HalfMd4Unsigned,
+    /// [`HashVersion::Tea`] over unsigned bytes
+    TeaUnsigned,
+}
+
+/// The hash of a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NameHash {
+    /// The hash the index is ordered by. The lowest bit is always clear.
+    pub major: u32,
+    /// A secondary hash, used to order names with the same major hash.
+    pub minor: u32,
+}
+
+impl HashVersion {
+    /// Decode a raw hash version, as stored in `def_hash_version`.
+    #[must_use]
+    pub const fn from_raw(raw: u8) -> Option<Self> {
+        Some(match raw {
+            0 => Self::Legacy,
+            1 => Self::HalfMd4,
+            2 => Self::Tea,
+            3 => Self::LegacyUnsigned,
+            4 => Self::HalfMd4Unsigned,
+            5 => Self::TeaUnsigned,
+            _ => return None,
+        })
+    }
+
+    /// The raw hash version.
+    #[must_use]
+    pub const fn to_raw(self) -> u8 {
+        self as u8
+    }
+
+    /// The variant of this hash which treats bytes as unsigned.
+    #[must_use]
+    pub const fn to_unsigned(self) -> Self {
+        match self {
+            Self::Legacy | Self::LegacyUnsigned => Self::LegacyUnsigned,
+            Self::HalfMd4 | Self::HalfMd4Unsigned => Self::HalfMd4Unsigned,
+            Self::Tea | Self::TeaUnsigned => Self::TeaUnsigned,
+        }
+    }
+
+    /// Hash `name` with `seed`, the superblock's `hash_seed`.
+    ///
+    /// A zero seed is replaced by a fixed default. The legacy hash ignores the seed and has no
+    /// minor hash.
+    #[must_use]
+    pub fn hash(self, name: &[u8], seed: [u32; 4]) -> NameHash {
+        let mut state = if seed == [0; 4] { DEFAULT_SEED } else { seed };
+        let signed = matches!(self, Self::Legacy | Self::HalfMd4 | Self::Tea);
+
+        let (major, minor) = match self {
+            Self::Legacy | Self::LegacyUnsigned => (legacy(name, signed), 0),
+            Self::HalfMd4 | Self::HalfMd4Unsigned => {
+                for (i, chunk) in name.chunks(32).enumerate() {
+                    let mut input = [0; 8];
+                    fill_input(chunk, name.len() - i * 32, signed, &mut input);
+                    half_md4_transform(&mut state, &input);
+                }
+                (state[1], state[2])
+            }
+            Self::Tea | Self::TeaUnsigned => {
+                for (i, chunk) in name.chunks(16).enumerate() {
+                    let mut input = [0; 4];
+                    fill_input(chunk, name.len() - i * 16, signed, &mut input);
+                    tea_transform(&mut state, &input);
+                }
+                (state[0], state[1])
+            }
+        };
+
+        let major = match major & !1 {
+            0xffff_fffe => EOF_HASH,
+            major => major,
+        };
+        NameHash { major, minor }
+    }
+}
+
+/// A byte of a name, widened as a C `char` of the given signedness would be.
+fn widen(byte: u8, signed: bool) -> u32 {
+    if signed {
+        byte as i8 as u32
+    } else {
+        u32::from(byte)
+    }
+}
+
+/// The legacy hash, which does not use the seed.
+fn legacy(name: &[u8], signed: bool) -> u32 {
+    let (mut hash0, mut hash1) = (0x12a3_fe2d_u32, 0x37ab_e8f9_u32);
+    for &byte in name {
+        let mut hash = hash1.wrapping_add(hash0 ^ widen(byte, signed).wrapping_mul(7_152_373));
+        if hash & 0x8000_0000 != 0 {
+            hash = hash.wrapping_sub(0x7fff_ffff);
+        }
+        hash1 = hash0;
+        hash0 = hash;
+    }
+    hash0 << 1
+}
+
+/// Pack `chunk`, the start of the last `remaining` bytes of a name, into the words of `input`,
+/// padding with a pattern made from `remaining`.
+fn fill_input(chunk: &[u8], remaining: usize, signed: bool, input: &mut [u32]) {
+    let pad = remaining as u32 | (remaining as u32) << 8;
+    let pad = pad | pad << 16;
+    let mut words = chunk.chunks(4);
+    for word in input.iter_mut() {
+        *word = match words.next() {
+            Some(bytes) => bytes
+                .iter()
+                .fold(pad, |val, &byte| widen(byte, signed).wrapping_add(val << 8)),
+            None => pad,
+        };
+    }
+}
+
+fn half_md4_transform(state: &mut [u32; 4], input: &[u32; 8]) {
+    const K2: u32 = 0o13_240_474_631;
+    const K3: u32 = 0o15_666_365_641;
+    let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+    let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+    let [mut a, mut b, mut c, mut d] = *state;
+    macro_rules! round {
+        ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+            $a = $a
+                .wrapping_add($f($b, $c, $d))
+                .wrapping_add($x)
+                .rotate_left($s);
+        };
+    }
+
+    round!(f, a, b, c, d, input[0], 3);
+    round!(f, d, a, b, c, input[1], 7);
+    round!(f, c, d, a, b, input[2], 11);
+    round!(f, b, c, d, a, input[3], 19);
+    round!(f, a, b, c, d, input[4], 3);
+    round!(f, d, a, b, c, input[5], 7);
+    round!(f, c, d, a, b, input[6], 11);
+    round!(f, b, c, d, a, input[7], 19);
+
+    round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+    round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+    round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+    round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+    for (word, value) in state.iter_mut().zip([a, b, c, d]) {
+        *word = word.wrapping_add(value);
+    }
+}
+
+fn tea_transform(state: &mut [u32; 4], input: &[u32; 4]) {
+    const DELTA: u32 = 0x9e37_79b9;
+    let [a, b, c, d] = *input;
+    let (mut b0, mut b1) = (state[0], state[1]);
+    let mut sum = 0_u32;
+
+    for _ in 0..16 {
+        sum = sum.wrapping_add(DELTA);
+        b0 = b0.wrapping_add(
+            (b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+        );
+        b1 = b1.wrapping_add(
+            (b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+        );
+    }
+    state[0] = state[0].wrapping_add(b0);
+    state[1] = state[1].wrapping_add(b1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The seed 5a0c1e1d-3d6e-4f2a-8b1c-9d7e6f5a4b3c, as stored in the superblock.
+    const SEED: [u32; 4] = [0x1d1e_0c5a, 0x2a4f_6e3d, 0x7e9d_1c8b, 0x3c4b_5a6f];
+    const LONG: &[u8] = b"a-name-that-is-longer-than-thirty-two-bytes-long";
+
+    fn hash(version: HashVersion, name: &[u8], seed: [u32; 4]) -> (u32, u32) {
+        let hash = version.hash(name, seed);
+        (hash.major, hash.minor)
+    }
+
+    // The expected values come from `debugfs -R "dx_hash -h <version> -s <seed> <name>"`.
+
+    #[test]
+    fn hashes_with_legacy() {
+        assert_eq!(
+            hash(HashVersion::Legacy, b"hello.txt", SEED),
+            (0x65a0_5776, 0)
+        );
+        assert_eq!(hash(HashVersion::Legacy, LONG, SEED), (0xece1_2fbc, 0));
+        assert_eq!(
+            hash(HashVersion::Legacy, "café".as_bytes(), SEED).0,
+            0x96ca_5a2c
+        );
+        assert_eq!(
+            hash(HashVersion::LegacyUnsigned, "café".as_bytes(), SEED).0,
+            0x6dde_4230
+        );
+    }
+
+    #[test]
+    fn hashes_with_half_md4() {
+        assert_eq!(
+            hash(HashVersion::HalfMd4, b"hello.txt", SEED),
+            (0x664e_d4ae, 0x3f0f_a422)
+        );
+        assert_eq!(
+            hash(HashVersion::HalfMd4, LONG, SEED),
+            (0xa363_7be2, 0x004f_d8c6)
+        );
+        assert_eq!(
+            hash(HashVersion::HalfMd4, "café".as_bytes(), SEED),
+            (0x5987_e32e, 0xf157_ce12)
+        );
+        assert_eq!(
+            hash(HashVersion::HalfMd4Unsigned, "café".as_bytes(), SEED),
+            (0x399f_d9e2, 0x822c_31ab)
+        );
+        assert_eq!(
+            hash(HashVersion::HalfMd4, b"hello.txt", [0; 4]),
+            (0xa26e_1d86, 0x133b_3f98)
+        );
+    }
+
+    #[test]
+    fn hashes_with_tea() {
+        assert_eq!(
+            hash(HashVersion::Tea, b"hello.txt", SEED),
+            (0xc223_a7f4, 0x6245_87c5)
+        );
+        assert_eq!(
+            hash(HashVersion::Tea, LONG, SEED),
+            (0xc7ab_2644, 0x5156_4e15)
+        );
+        assert_eq!(
+            hash(HashVersion::Tea, "café".as_bytes(), SEED),
+            (0xa74a_86e4, 0x2ef8_0271)
+        );
+        assert_eq!(
+            hash(HashVersion::TeaUnsigned, "café".as_bytes(), SEED),
+            (0xed73_1a4e, 0xe9a2_e5bb)
+        );
+    }
+
+    #[test]
+    fn converts_versions() {
+        assert_eq!(HashVersion::from_raw(1), Some(HashVersion::HalfMd4));
+        assert_eq!(HashVersion::from_raw(6), None);
+        assert_eq!(HashVersion::Tea.to_unsigned(), HashVersion::TeaUnsigned);
+        assert_eq!(HashVersion::TeaUnsigned.to_raw(), 5);
+    }
+}
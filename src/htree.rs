@@ -0,0 +1,843 @@
+//! Look up and add names in directories indexed by hash trees.
+//!
+//! An indexed directory's first block holds, after the `.` and `..` entries, the root of a tree
+//! of [`DxEntry`] arrays sorted by name hash. The leaves are ordinary directory blocks, each
+//! holding the names with hashes in a range, so a lookup reads one block per level instead of
+//! the whole directory. The index is hidden from readers which do not know about it: the root
+//! sits in the slack of the `..` record, and index nodes look like blocks with one deleted
+//! record.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::{self, size_of};
+
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::device::BlockDevice;
+use crate::dir::{record_len, Dir, DirEntry, Located};
+use crate::error::{Error, Result};
+use crate::features::CompatFeatures;
+use crate::fs::Ext2;
+use crate::hash::{HashVersion, NameHash};
+use crate::schema::{
+    DxCountLimit, DxEntry, DxRootInfo, Inode, InodeFlags, InodeNumber, Superblock, TypeIndicator,
+    EXT2_FLAGS_UNSIGNED_HASH,
+};
+
+/// The most levels of index nodes below the root.
+pub(crate) const MAX_INDIRECT_LEVELS: u8 = 1;
+
+/// The offset of the root's [`DxRootInfo`], after the `.` and `..` entries.
+pub(crate) const ROOT_INFO_OFFSET: usize = 24;
+
+/// The offset of the entries in an index node, after a record covering the whole block.
+pub(crate) const NODE_ENTRIES_OFFSET: usize = 8;
+
+/// The bits of [`DxEntry::block`] holding the block number.
+const BLOCK_MASK: u32 = 0x00ff_ffff;
+
+/// One level of the path from the root of a hash tree to a leaf.
+#[derive(Debug, Clone)]
+pub(crate) struct DxFrame {
+    /// The logical block holding the index entries.
+    pub logical: u32,
+    /// The offset of the entries in the block.
+    pub offset: usize,
+    /// The number of entries the block has room for.
+    pub limit: usize,
+    /// The hashes and logical blocks of the entries, with 0 as the first entry's hash.
+    pub entries: Vec<(u32, u32)>,
+    /// The index of the entry the path follows.
+    pub at: usize,
+}
+
+/// The path through a hash tree to the leaf which may hold a name.
+#[derive(Debug, Clone)]
+pub(crate) struct DxPath {
+    /// The hash function the tree is ordered by.
+    pub version: HashVersion,
+    /// The hash of the name.
+    pub hash: NameHash,
+    /// The frames from the root down.
+    pub frames: Vec<DxFrame>,
+}
+
+impl DxPath {
+    /// The logical block of the leaf the path leads to.
+    pub(crate) fn leaf(&self) -> u32 {
+        let frame = self.frames.last().expect("paths have a root");
+        frame.entries[frame.at].1
+    }
+}
+
+/// Where [`Ext2::next_leaf`] moved a path.
+pub(crate) enum NextLeaf {
+    /// To a leaf starting at the hash.
+    Moved(u32),
+    /// Nowhere, since the path was at the last leaf.
+    End,
+    /// Nowhere, since an index node is malformed.
+    BadIndex,
+}
+
+/// The outcome of searching a hash tree.
+enum Search {
+    Found(Located),
+    Missing,
+    /// The index is malformed, and the directory must be searched linearly.
+    BadIndex,
+}
+
+impl Superblock {
+    /// The seed for hashing names in indexed directories.
+    #[must_use]
+    pub fn hash_seed(&self) -> [u32; 4] {
+        self.hash_seed.map(|word| word.get())
+    }
+
+    /// Adjust the hash version stored in a hash tree root for the signedness recorded in the
+    /// superblock's `flags`.
+    pub(crate) fn effective_hash_version(&self, version: HashVersion) -> HashVersion {
+        if self.flags.get() & EXT2_FLAGS_UNSIGNED_HASH != 0 {
+            version.to_unsigned()
+        } else {
+            version
+        }
+    }
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Find the entry named `name` in the directory at inode number `dir`, returning the inode
+    /// it points to.
+    ///
+    /// If the directory is indexed by a hash tree, only the blocks on the path to the name's
+    /// leaf are read. Otherwise, or if the index is malformed, the whole directory is searched.
+    ///
+    /// # Errors
+    /// If the inode cannot be read or is not a directory, or the directory is malformed.
+    pub fn find_entry(&self, dir: InodeNumber, name: &[u8]) -> Result<Option<InodeNumber>> {
+        let inode = self.inode(dir)?;
+        Ok(self
+            .locate_entry(dir, &inode, name)?
+            .map(|found| found.inode))
+    }
+
+    /// Find the record of the entry named `name` in the directory at inode number `dir`, whose
+    /// inode is `inode`, as [`Ext2::find_entry`] does.
+    ///
+    /// # Errors
+    /// If the inode is not a directory, or the directory is malformed.
+    pub(crate) fn locate_entry(
+        &self,
+        dir: InodeNumber,
+        inode: &Inode,
+        name: &[u8],
+    ) -> Result<Option<Located>> {
+        if !inode.is_dir() {
+            return Err(Error::NotADirectory(dir));
+        }
+
+        if self.is_indexed(inode) {
+            // `.` and `..` are only in the first block, outside of the leaves.
+            if matches!(name, b"." | b"..") {
+                return self.read_dir_block(dir, inode, 0)?.locate(name);
+            }
+            match self.dx_find(dir, inode, name)? {
+                Search::Found(found) => return Ok(Some(found)),
+                Search::Missing => return Ok(None),
+                Search::BadIndex => {}
+            }
+        }
+        self.read_dir(dir)?.locate(name)
+    }
+
+    /// Whether the directory with inode `inode` is indexed by a hash tree which this filesystem
+    /// should use.
+    pub(crate) fn is_indexed(&self, inode: &Inode) -> bool {
+        inode.inode_flags().contains(InodeFlags::INDEX)
+            && self
+                .superblock()
+                .compat_features()
+                .contains(CompatFeatures::DIR_INDEX)
+    }
+
+    /// Follow the hash tree of the directory with inode `inode` towards the leaf which may hold
+    /// `name`.
+    ///
+    /// Returns `None` if the index is malformed.
+    ///
+    /// # Errors
+    /// If the index blocks cannot be read.
+    pub(crate) fn dx_probe(&self, inode: &Inode, name: &[u8]) -> Result<Option<DxPath>> {
+        let Some(root) = self.read_index_block(inode, 0)? else {
+            return Ok(None);
+        };
+        let info =
+            DxRootInfo::read_from_prefix(&root[ROOT_INFO_OFFSET..]).expect("fits in a block");
+        let version = match info.hash_version {
+            0..=2 => HashVersion::from_raw(info.hash_version).expect("known version"),
+            _ => return Ok(None),
+        };
+        if info.reserved_zero.get() != 0
+            || usize::from(info.info_length) != size_of::<DxRootInfo>()
+            || info.indirect_levels > MAX_INDIRECT_LEVELS
+        {
+            return Ok(None);
+        }
+
+        let version = self.superblock().effective_hash_version(version);
+        let hash = version.hash(name, self.superblock().hash_seed());
+        let mut frames = Vec::new();
+        let mut block = root;
+        let mut logical = 0;
+        let mut offset = ROOT_INFO_OFFSET + size_of::<DxRootInfo>();
+        for level in 0..=info.indirect_levels {
+            let Some(mut frame) = self.parse_frame(inode, logical, &block, offset) else {
+                return Ok(None);
+            };
+            frame.at = frame.entries[1..].partition_point(|&(start, _)| start <= hash.major);
+            let child = frame.entries[frame.at].1;
+            frames.push(frame);
+
+            if level < info.indirect_levels {
+                let Some(node) = self.read_index_block(inode, child)? else {
+                    return Ok(None);
+                };
+                block = node;
+                logical = child;
+                offset = NODE_ENTRIES_OFFSET;
+            }
+        }
+
+        Ok(Some(DxPath {
+            version,
+            hash,
+            frames,
+        }))
+    }
+
+    /// Search the leaves of the hash tree of directory `dir` which may hold `name`.
+    fn dx_find(&self, dir: InodeNumber, inode: &Inode, name: &[u8]) -> Result<Search> {
+        let Some(mut path) = self.dx_probe(inode, name)? else {
+            return Ok(Search::BadIndex);
+        };
+        loop {
+            let leaf = self.read_dir_block(dir, inode, path.leaf())?;
+            if let Some(found) = leaf.locate(name)? {
+                return Ok(Search::Found(found));
+            }
+            // Names with the same hash may continue in the next leaf, whose hash then has the
+            // lowest bit set.
+            match self.next_leaf(inode, &mut path)? {
+                NextLeaf::Moved(start) if start & !1 == path.hash.major => {}
+                NextLeaf::Moved(_) | NextLeaf::End => return Ok(Search::Missing),
+                NextLeaf::BadIndex => return Ok(Search::BadIndex),
+            }
+        }
+    }
+
+    /// Move `path` to the next leaf in hash order.
+    ///
+    /// # Errors
+    /// If an index node cannot be read.
+    pub(crate) fn next_leaf(&self, inode: &Inode, path: &mut DxPath) -> Result<NextLeaf> {
+        let Some(level) = path
+            .frames
+            .iter()
+            .rposition(|frame| frame.at + 1 < frame.entries.len())
+        else {
+            return Ok(NextLeaf::End);
+        };
+        path.frames[level].at += 1;
+        let frame = &path.frames[level];
+        let (start, mut child) = frame.entries[frame.at];
+
+        // The frames below the one which moved start again from their first entry.
+        for lower in level + 1..path.frames.len() {
+            let Some(node) = self.read_index_block(inode, child)? else {
+                return Ok(NextLeaf::BadIndex);
+            };
+            let Some(frame) = self.parse_frame(inode, child, &node, NODE_ENTRIES_OFFSET) else {
+                return Ok(NextLeaf::BadIndex);
+            };
+            child = frame.entries[0].1;
+            path.frames[lower] = frame;
+        }
+        Ok(NextLeaf::Moved(start))
+    }
+
+    /// Read logical block `logical` of a directory, returning `None` if it is a hole.
+    fn read_index_block(&self, inode: &Inode, logical: u32) -> Result<Option<Vec<u8>>> {
+        let Some(block) = inode.block_map(self, logical)? else {
+            return Ok(None);
+        };
+        let mut data = vec![0; self.block_size()];
+        self.read_block_at(block, 0, &mut data)?;
+        Ok(Some(data))
+    }
+
+    /// Parse the index entries at `offset` in logical block `logical` of a directory, returning
+    /// `None` if they are malformed.
+    fn parse_frame(
+        &self,
+        inode: &Inode,
+        logical: u32,
+        data: &[u8],
+        offset: usize,
+    ) -> Option<DxFrame> {
+        let header = DxCountLimit::read_from_prefix(&data[offset..])?;
+        let limit = usize::from(header.limit.get());
+        let count = usize::from(header.count.get());
+        let blocks = inode.size() / self.block_size() as u64;
+        if limit != (data.len() - offset) / size_of::<DxEntry>() || count == 0 || count > limit {
+            return None;
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for (index, raw) in data[offset..]
+            .chunks_exact(size_of::<DxEntry>())
+            .take(count)
+            .enumerate()
+        {
+            let entry = DxEntry::read_from(raw).expect("chunk is exact");
+            let hash = if index == 0 { 0 } else { entry.hash.get() };
+            let block = entry.block.get() & BLOCK_MASK;
+            if u64::from(block) >= blocks {
+                return None;
+            }
+            entries.push((hash, block));
+        }
+        Some(DxFrame {
+            logical,
+            offset,
+            limit,
+            entries,
+            at: 0,
+        })
+    }
+}
+
+/// Where to split a full leaf, whose entries have the hashes and record lengths in `entries`,
+/// sorted by hash: the index of the first entry to move to a new leaf, and whether its hash is
+/// the same as the one before it.
+///
+/// The entries making up about half of the block are moved, as Linux does. There must be at
+/// least two entries.
+fn split_point(entries: &[(u32, usize)], block_size: usize) -> (usize, bool) {
+    let mut size = 0;
+    let mut moved = 0;
+    for &(_, len) in entries.iter().rev() {
+        if size + len / 2 > block_size / 2 {
+            break;
+        }
+        size += len;
+        moved += 1;
+    }
+    let split = (entries.len() - moved).clamp(1, entries.len() - 1);
+    (split, entries[split].0 == entries[split - 1].0)
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Whether the directory with inode `inode` should be indexed before it grows past its
+    /// first block.
+    pub(crate) fn should_index(&self, inode: &Inode) -> bool {
+        self.index_directories()
+            && inode.size() == self.block_size() as u64
+            && self
+                .superblock()
+                .compat_features()
+                .contains(CompatFeatures::DIR_INDEX)
+    }
+
+    /// Add an entry for `name` to the indexed directory at inode number `dir`, in the leaf for
+    /// its hash, splitting the leaf if it is full.
+    ///
+    /// Returns `false`, having changed nothing, if the index is malformed. The size and block
+    /// pointers of `dir_inode` are updated, and the caller must write it back.
+    pub(crate) fn dx_add_entry(
+        &mut self,
+        dir: InodeNumber,
+        dir_inode: &mut Inode,
+        name: &[u8],
+        inode: InodeNumber,
+        indicator: TypeIndicator,
+    ) -> Result<bool> {
+        let Some(mut path) = self.dx_probe(dir_inode, name)? else {
+            return Ok(false);
+        };
+        let needed = record_len(name.len());
+        let block_size = self.block_size();
+
+        let leaf = self.read_dir_block(dir, dir_inode, path.leaf())?;
+        let (target, slot) = if let Some(slot) = leaf.find_slot(needed)? {
+            (path.leaf(), slot)
+        } else {
+            self.make_index_room(dir, dir_inode, &mut path)?;
+            let (split_hash, new_leaf) = self.split_leaf(dir, dir_inode, &mut path, &leaf)?;
+            let target = if path.hash.major >= split_hash & !1 {
+                new_leaf
+            } else {
+                path.leaf()
+            };
+            let leaf = self.read_dir_block(dir, dir_inode, target)?;
+            (target, leaf.find_slot(needed)?.ok_or(Error::NoSpace)?)
+        };
+
+        let (block, _) = self.entry_location(dir, dir_inode, target as usize * block_size)?;
+        self.fill_slot(block, slot.offset, slot, name, inode, indicator)?;
+        Ok(true)
+    }
+
+    /// Index the single-block directory at inode number `dir`, moving its entries other than `.`
+    /// and `..` to a new leaf, and putting the root of a hash tree after `..`.
+    ///
+    /// The size, block pointers and flags of `dir_inode` are updated, and the caller must write
+    /// it back.
+    pub(crate) fn make_indexed(&mut self, dir: InodeNumber, dir_inode: &mut Inode) -> Result<()> {
+        let block_size = self.block_size();
+        let first = self.read_dir_block(dir, dir_inode, 0)?;
+        let corrupt = Error::CorruptDirectory {
+            number: dir,
+            offset: 0,
+        };
+        let dot = first.find(b".")?.ok_or(corrupt)?;
+        let dotdot = first.find(b"..")?.ok_or(corrupt)?;
+        let rest = first
+            .iter()
+            .filter(|entry| !matches!(entry, Ok(entry) if matches!(entry.name, b"." | b"..")))
+            .collect::<Result<Vec<_>>>()?;
+
+        let (root_block, _) = self.entry_location(dir, dir_inode, 0)?;
+        let (leaf, leaf_block) = self.append_dir_block(dir, dir_inode)?;
+        let data = self.pack_entries(&rest);
+        self.write_block_at(leaf_block, 0, &data)?;
+
+        let version = HashVersion::from_raw(self.superblock().def_hash_version)
+            .filter(|version| version.to_raw() <= HashVersion::Tea.to_raw())
+            .unwrap_or(HashVersion::HalfMd4);
+        let mut info = DxRootInfo::new_zeroed();
+        info.hash_version = version.to_raw();
+        info.info_length = size_of::<DxRootInfo>() as u8;
+
+        let dot_len = record_len(1);
+        let mut root = self.entry_bytes(b".", dot.inode, dot.file_type, dot_len);
+        root.resize(dot_len, 0);
+        root.extend(self.entry_bytes(b"..", dotdot.inode, dotdot.file_type, block_size - dot_len));
+        root.resize(ROOT_INFO_OFFSET, 0);
+        root.extend_from_slice(info.as_bytes());
+        root.resize(block_size, 0);
+        self.write_block_at(root_block, 0, &root)?;
+
+        let offset = ROOT_INFO_OFFSET + size_of::<DxRootInfo>();
+        let frame = DxFrame {
+            logical: 0,
+            offset,
+            limit: (block_size - offset) / size_of::<DxEntry>(),
+            entries: vec![(0, leaf)],
+            at: 0,
+        };
+        self.write_frame(dir, dir_inode, &frame)?;
+        dir_inode.set_inode_flags(dir_inode.inode_flags() | InodeFlags::INDEX);
+        Ok(())
+    }
+
+    /// Make room for another entry in the deepest index block on `path`, by adding a level of
+    /// index nodes below the root or by splitting a node.
+    ///
+    /// # Errors
+    /// [`Error::NoSpace`] if the index already has the most levels allowed, and is full.
+    fn make_index_room(
+        &mut self,
+        dir: InodeNumber,
+        dir_inode: &mut Inode,
+        path: &mut DxPath,
+    ) -> Result<()> {
+        let depth = path.frames.len() - 1;
+        let full = |frame: &DxFrame| frame.entries.len() >= frame.limit;
+        if !full(&path.frames[depth]) {
+            return Ok(());
+        }
+        if depth > 0 && full(&path.frames[0]) {
+            return Err(Error::NoSpace);
+        }
+
+        let limit = (self.block_size() - NODE_ENTRIES_OFFSET) / size_of::<DxEntry>();
+        let (logical, _) = self.append_dir_block(dir, dir_inode)?;
+        let mut node = DxFrame {
+            logical,
+            offset: NODE_ENTRIES_OFFSET,
+            limit,
+            entries: Vec::new(),
+            at: 0,
+        };
+
+        if depth == 0 {
+            // Move the root's entries to the new node, and point the root at it alone.
+            let root = &mut path.frames[0];
+            node.entries = mem::replace(&mut root.entries, vec![(0, logical)]);
+            node.at = mem::replace(&mut root.at, 0);
+            self.init_node(dir, dir_inode, &node)?;
+            self.write_frame(dir, dir_inode, &path.frames[0])?;
+            let (root_block, _) = self.entry_location(dir, dir_inode, 0)?;
+            self.write_block_at(root_block, ROOT_INFO_OFFSET + 6, &[1])?;
+            path.frames.push(node);
+            return Ok(());
+        }
+
+        // Move the upper half of the full node to the new node, and add that to the root.
+        let [root, old] = &mut path.frames[..] else {
+            unreachable!("index trees have at most two levels");
+        };
+        let half = old.entries.len() / 2;
+        node.entries = old.entries.split_off(half);
+        let split_hash = mem::replace(&mut node.entries[0].0, 0);
+        root.entries.insert(root.at + 1, (split_hash, logical));
+        if old.at >= half {
+            node.at = old.at - half;
+            root.at += 1;
+            mem::swap(old, &mut node);
+        }
+        self.init_node(dir, dir_inode, &path.frames[1])?;
+        self.init_node(dir, dir_inode, &node)?;
+        self.write_frame(dir, dir_inode, &path.frames[0])
+    }
+
+    /// Move about half of the entries of the full leaf `leaf`, at the end of `path`, to a new
+    /// leaf, and add the new leaf to the index.
+    ///
+    /// Returns the hash the new leaf starts at, which has its lowest bit set if names with the
+    /// same hash are split between the leaves, and the new leaf's logical block.
+    fn split_leaf(
+        &mut self,
+        dir: InodeNumber,
+        dir_inode: &mut Inode,
+        path: &mut DxPath,
+        leaf: &Dir,
+    ) -> Result<(u32, u32)> {
+        let block_size = self.block_size();
+        let seed = self.superblock().hash_seed();
+        let mut entries = leaf
+            .iter()
+            .map(|entry| entry.map(|entry| (path.version.hash(entry.name, seed), entry)))
+            .collect::<Result<Vec<_>>>()?;
+        if entries.len() < 2 {
+            return Err(Error::CorruptDirectory {
+                number: dir,
+                offset: path.leaf() as usize * block_size,
+            });
+        }
+        entries.sort_by_key(|(hash, _)| (hash.major, hash.minor));
+        let sizes: Vec<_> = entries
+            .iter()
+            .map(|(hash, entry)| (hash.major, record_len(entry.name.len())))
+            .collect();
+        let (split, continued) = split_point(&sizes, block_size);
+        let split_hash = entries[split].0.major | u32::from(continued);
+        let entries: Vec<_> = entries.into_iter().map(|(_, entry)| entry).collect();
+
+        let (old_block, _) =
+            self.entry_location(dir, dir_inode, path.leaf() as usize * block_size)?;
+        let (new_leaf, new_block) = self.append_dir_block(dir, dir_inode)?;
+        let data = self.pack_entries(&entries[split..]);
+        self.write_block_at(new_block, 0, &data)?;
+        let data = self.pack_entries(&entries[..split]);
+        self.write_block_at(old_block, 0, &data)?;
+
+        let frame = path.frames.last_mut().expect("paths have a root");
+        frame.entries.insert(frame.at + 1, (split_hash, new_leaf));
+        let frame = frame.clone();
+        self.write_frame(dir, dir_inode, &frame)?;
+        Ok((split_hash, new_leaf))
+    }
+
+    /// A directory block holding `entries`, with the last record covering the rest of the block.
+    fn pack_entries(&self, entries: &[DirEntry<'_>]) -> Vec<u8> {
+        let block_size = self.block_size();
+        let mut data = Vec::with_capacity(block_size);
+        for (index, entry) in entries.iter().enumerate() {
+            let used = record_len(entry.name.len());
+            let len = if index + 1 == entries.len() {
+                block_size - data.len()
+            } else {
+                used
+            };
+            let start = data.len();
+            data.extend(self.entry_bytes(entry.name, entry.inode, entry.file_type, len));
+            data.resize(start + used, 0);
+        }
+        if entries.is_empty() {
+            // A single deleted record covering the block.
+            data.extend(self.entry_bytes(b"", 0, TypeIndicator::Unknown, block_size));
+        }
+        data.resize(block_size, 0);
+        data
+    }
+
+    /// Write the new index node `frame`, behind a deleted record covering its whole block.
+    fn init_node(&mut self, dir: InodeNumber, dir_inode: &Inode, frame: &DxFrame) -> Result<()> {
+        let block_size = self.block_size();
+        let (block, _) =
+            self.entry_location(dir, dir_inode, frame.logical as usize * block_size)?;
+        let mut data = self.entry_bytes(b"", 0, TypeIndicator::Unknown, block_size);
+        data.resize(block_size, 0);
+        self.write_block_at(block, 0, &data)?;
+        self.write_frame(dir, dir_inode, frame)
+    }
+
+    /// Write the entries of `frame` to its block.
+    fn write_frame(&mut self, dir: InodeNumber, dir_inode: &Inode, frame: &DxFrame) -> Result<()> {
+        let block_size = self.block_size();
+        let (block, _) =
+            self.entry_location(dir, dir_inode, frame.logical as usize * block_size)?;
+        let mut header = DxCountLimit::new_zeroed();
+        header.limit.set(frame.limit as u16);
+        header.count.set(frame.entries.len() as u16);
+
+        let mut data = header.as_bytes().to_vec();
+        for (index, &(hash, logical)) in frame.entries.iter().enumerate() {
+            if index > 0 {
+                data.extend_from_slice(&hash.to_le_bytes());
+            }
+            data.extend_from_slice(&logical.to_le_bytes());
+        }
+        self.write_block_at(block, frame.offset, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+    use crate::mode::{FileType, Permissions};
+    use crate::schema::EXT2_ROOT_INO;
+
+    /// An image with `/big`, a directory of 600 entries indexed by a two-level hash tree, and
+    /// `/small`, a directory of 80 entries indexed by a one-level tree.
+    const IMAGE: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/resources/htree.ext2"
+    ));
+
+    fn big_name(index: usize) -> Vec<u8> {
+        let mut name = alloc::format!("entry-{index:04}-").into_bytes();
+        name.resize(name.len() + 200, b'x');
+        name
+    }
+
+    #[test]
+    fn finds_every_entry() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        for path in ["/big", "/small"] {
+            let dir = fs.lookup(path).unwrap();
+            assert!(fs.is_indexed(&fs.inode(dir).unwrap()));
+            for entry in &fs.read_dir(dir).unwrap() {
+                let entry = entry.unwrap();
+                assert_eq!(fs.find_entry(dir, entry.name).unwrap(), Some(entry.inode));
+            }
+        }
+    }
+
+    #[test]
+    fn descends_two_levels() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let dir = fs.lookup("/big").unwrap();
+        let inode = fs.inode(dir).unwrap();
+        let path = fs.dx_probe(&inode, &big_name(123)).unwrap().unwrap();
+
+        assert_eq!(path.frames.len(), 2);
+        let leaf = fs.read_dir_block(dir, &inode, path.leaf()).unwrap();
+        assert!(leaf.find(&big_name(123)).unwrap().is_some());
+        assert_eq!(fs.find_entry(dir, &big_name(600)).unwrap(), None);
+        assert_eq!(fs.find_entry(dir, b"..").unwrap(), Some(EXT2_ROOT_INO));
+        assert_eq!(fs.find_entry(dir, b".").unwrap(), Some(dir));
+    }
+
+    #[test]
+    fn removes_and_replaces_entries_found_through_the_index() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let dir = fs.lookup("/big").unwrap();
+        let inode = fs.inode(dir).unwrap();
+        let path = fs.dx_probe(&inode, &big_name(123)).unwrap().unwrap();
+        let found = fs
+            .locate_entry(dir, &inode, &big_name(123))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.offset / fs.block_size(), path.leaf() as usize);
+        let moved = fs.find_entry(dir, &big_name(124)).unwrap().unwrap();
+
+        fs.unlink(dir, big_name(123)).unwrap();
+        fs.rename(dir, big_name(124), dir, big_name(123)).unwrap();
+
+        assert!(fs.is_indexed(&fs.inode(dir).unwrap()));
+        assert_eq!(fs.find_entry(dir, &big_name(124)).unwrap(), None);
+        assert_eq!(fs.find_entry(dir, &big_name(123)).unwrap(), Some(moved));
+        assert_eq!(fs.read_dir(dir).unwrap().iter().count(), 2 + 599);
+    }
+
+    #[test]
+    fn walks_leaves_in_hash_order() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let dir = fs.lookup("/big").unwrap();
+        let inode = fs.inode(dir).unwrap();
+        let version = fs.superblock().effective_hash_version(HashVersion::HalfMd4);
+        let seed = fs.superblock().hash_seed();
+        let first = (0..600)
+            .map(big_name)
+            .min_by_key(|name| version.hash(name, seed).major)
+            .unwrap();
+
+        let mut path = fs.dx_probe(&inode, &first).unwrap().unwrap();
+        let mut names = 0;
+        let mut last_start = 0;
+        loop {
+            names += fs
+                .read_dir_block(dir, &inode, path.leaf())
+                .unwrap()
+                .iter()
+                .count();
+            match fs.next_leaf(&inode, &mut path).unwrap() {
+                NextLeaf::Moved(start) => {
+                    assert!(start >= last_start);
+                    last_start = start;
+                }
+                NextLeaf::End => break,
+                NextLeaf::BadIndex => panic!("index is valid"),
+            }
+        }
+        assert_eq!(names, 600);
+    }
+
+    #[test]
+    fn falls_back_to_a_linear_search() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let dir = fs.lookup("/big").unwrap();
+        let inode = fs.inode(dir).unwrap();
+        let root = inode.block_map(&fs, 0).unwrap().unwrap();
+        // An index with more levels than ext2 allows is rejected.
+        fs.write_block_at(root, ROOT_INFO_OFFSET + 6, &[2]).unwrap();
+
+        assert!(fs.dx_probe(&inode, &big_name(5)).unwrap().is_none());
+        assert!(fs.find_entry(dir, &big_name(5)).unwrap().is_some());
+        assert_eq!(fs.find_entry(dir, b"missing").unwrap(), None);
+    }
+
+    #[test]
+    fn ignores_the_index_without_the_feature() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut superblock = fs.superblock().clone();
+        let compat = superblock.compat_features() - CompatFeatures::DIR_INDEX;
+        superblock.features_opt.set(compat.bits());
+        fs.write_superblock(superblock).unwrap();
+        let dir = fs.lookup("/small").unwrap();
+
+        assert!(!fs.is_indexed(&fs.inode(dir).unwrap()));
+        assert!(fs.find_entry(dir, b"file-42").unwrap().is_some());
+    }
+
+    fn long_name(prefix: &str, index: usize) -> Vec<u8> {
+        let mut name = alloc::format!("{prefix}-{index:04}-").into_bytes();
+        name.resize(name.len() + 200, b'y');
+        name
+    }
+
+    /// Add entries named by `long_name(prefix, _)` to `dir`, all pointing at `target`, and check
+    /// every entry in the directory can be found through the index.
+    fn add_and_check(fs: &mut Ext2<&mut [u8]>, dir: InodeNumber, prefix: &str, count: usize) {
+        let target = fs.find_entry(dir, b"..").unwrap().unwrap();
+        for index in 0..count {
+            fs.add_entry(dir, &long_name(prefix, index), target, FileType::Directory)
+                .unwrap();
+        }
+        let inode = fs.inode(dir).unwrap();
+        assert!(fs.is_indexed(&inode));
+        for entry in &fs.read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            assert_eq!(fs.find_entry(dir, entry.name).unwrap(), Some(entry.inode));
+        }
+    }
+
+    #[test]
+    fn splits_leaves_and_grows_a_level() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let dir = fs.lookup("/small").unwrap();
+        add_and_check(&mut fs, dir, "new", 500);
+
+        let inode = fs.inode(dir).unwrap();
+        let path = fs.dx_probe(&inode, &long_name("new", 7)).unwrap().unwrap();
+        assert_eq!(path.frames.len(), 2);
+        assert_eq!(fs.read_dir(dir).unwrap().iter().count(), 2 + 80 + 500);
+    }
+
+    #[test]
+    fn splits_index_nodes() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let dir = fs.lookup("/big").unwrap();
+        let inode = fs.inode(dir).unwrap();
+        let nodes = fs.dx_probe(&inode, b"x").unwrap().unwrap().frames[0]
+            .entries
+            .len();
+        add_and_check(&mut fs, dir, "new", 600);
+
+        let inode = fs.inode(dir).unwrap();
+        let path = fs.dx_probe(&inode, b"x").unwrap().unwrap();
+        assert!(path.frames[0].entries.len() > nodes);
+    }
+
+    #[test]
+    fn indexes_a_directory_outgrowing_its_block() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let perms = Permissions::from_bits_retain(0o755);
+        let dir = fs.mkdir(EXT2_ROOT_INO, b"grown", perms).unwrap();
+        add_and_check(&mut fs, dir, "new", 20);
+        assert!(fs
+            .inode(dir)
+            .unwrap()
+            .inode_flags()
+            .contains(InodeFlags::INDEX));
+
+        fs.set_index_directories(false);
+        let dir = fs.mkdir(EXT2_ROOT_INO, b"linear", perms).unwrap();
+        let target = fs.find_entry(dir, b"..").unwrap().unwrap();
+        for index in 0..20 {
+            fs.add_entry(dir, &long_name("new", index), target, FileType::Directory)
+                .unwrap();
+        }
+        let inode = fs.inode(dir).unwrap();
+        assert!(inode.size() > fs.block_size() as u64);
+        assert!(!inode.inode_flags().contains(InodeFlags::INDEX));
+    }
+
+    #[test]
+    fn drops_a_corrupt_index_when_adding() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let dir = fs.lookup("/small").unwrap();
+        let inode = fs.inode(dir).unwrap();
+        let root = inode.block_map(&fs, 0).unwrap().unwrap();
+        fs.write_block_at(root, ROOT_INFO_OFFSET + 6, &[2]).unwrap();
+        fs.add_entry(dir, b"extra", 12, FileType::Regular).unwrap();
+
+        assert!(!fs
+            .inode(dir)
+            .unwrap()
+            .inode_flags()
+            .contains(InodeFlags::INDEX));
+        assert_eq!(fs.find_entry(dir, b"extra").unwrap(), Some(12));
+        assert!(fs.find_entry(dir, b"file-42").unwrap().is_some());
+    }
+
+    #[test]
+    fn marks_splits_inside_a_run_of_equal_hashes() {
+        let run = [(10, 200), (20, 200), (20, 200), (20, 200), (30, 200)];
+        assert_eq!(split_point(&run, 1024), (2, true));
+
+        let distinct = [(10, 16), (20, 16), (30, 16), (40, 16)];
+        assert_eq!(split_point(&distinct, 1024), (1, false));
+    }
+}
@@ -0,0 +1,288 @@
+//! Allocate and free inodes using the block group bitmaps.
+//!
+//! New directories are spread across block groups with the Orlov allocator used by Linux, so
+//! unrelated directory trees end up in different groups, while files and subdirectories stay
+//! near their parent.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bitmap;
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::schema::{InodeNumber, EXT2_ROOT_INO};
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Allocate an inode for a new child of the directory at inode number `parent`, returning its
+    /// number.
+    ///
+    /// Set `directory` if the new inode will be a directory, so the group's directory count is
+    /// kept up to date and the directory is placed by the Orlov allocator. Reserved inodes, below
+    /// [`Superblock::first_usable_inode`](crate::Superblock::first_usable_inode), are never
+    /// allocated.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidInode`] if `parent` is not a valid inode number.
+    /// - [`Error::NoSpace`] if every inode is in use.
+    /// - [`Error::CorruptBitmap`] if a group's bitmap disagrees with its free inode count.
+    /// - If the bitmaps cannot be read, or the bitmaps and counts cannot be written.
+    pub fn allocate_inode(&mut self, parent: InodeNumber, directory: bool) -> Result<InodeNumber> {
+        let parent_group = self.inode_group(parent)?;
+        let group = if directory {
+            self.find_group_orlov(parent, parent_group)
+        } else {
+            self.find_group_other(parent, parent_group)
+        }
+        .ok_or(Error::NoSpace)?;
+
+        let sb = self.superblock();
+        let per_group = sb.inodes_per_group.get();
+        let first = sb.first_usable_inode();
+        let start = first.saturating_sub(1 + group * per_group).min(per_group) as usize;
+
+        let mut bits = self.inode_bitmap(group)?;
+        let bit = bitmap::find_clear(&bits, start, per_group as usize)
+            .ok_or(Error::CorruptBitmap { group })?;
+        bitmap::set(&mut bits, bit);
+        self.write_inode_bitmap(group, &bits)?;
+        self.adjust_free_inodes(group, -1, directory)?;
+
+        Ok(group * per_group + bit as u32 + 1)
+    }
+
+    /// Free inode number `number`, making it available for allocation again.
+    ///
+    /// Set `directory` if the inode was allocated as a directory, so the group's directory count
+    /// is kept up to date. It is passed in rather than read from the inode, which may not have
+    /// been written yet if creating it failed.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidInode`] if `number` is not a valid inode number, or is reserved.
+    /// - [`Error::CorruptBitmap`] if the inode is already free.
+    /// - If the bitmap cannot be read, or the bitmap and counts cannot be written.
+    pub fn free_inode(&mut self, number: InodeNumber, directory: bool) -> Result<()> {
+        let group = self.inode_group(number)?;
+        if number < self.superblock().first_usable_inode() {
+            return Err(Error::InvalidInode(number));
+        }
+        let bit = ((number - 1) % self.superblock().inodes_per_group.get()) as usize;
+
+        let mut bits = self.inode_bitmap(group)?;
+        if !bitmap::test(&bits, bit) {
+            return Err(Error::CorruptBitmap { group });
+        }
+        bitmap::clear(&mut bits, bit);
+        self.write_inode_bitmap(group, &bits)?;
+        self.adjust_free_inodes(group, 1, directory)
+    }
+
+    /// Choose a group for a new directory.
+    ///
+    /// Top-level directories go to the group with the fewest directories among those with at
+    /// least the average number of free inodes and blocks. Other directories go to the first
+    /// group after their parent's which is not already crowded with directories or short of
+    /// space. As in Linux, groups with no free inodes are never chosen, even when the average is
+    /// below one.
+    fn find_group_orlov(&self, parent: InodeNumber, parent_group: u32) -> Option<u32> {
+        let sb = self.superblock();
+        let count = self.group_count();
+        let free_inodes = sb.free_inodes_count.get() / count;
+        let free_blocks = sb.free_blocks_count.get() / count;
+        let dirs: u32 = self
+            .groups()
+            .iter()
+            .map(|desc| u32::from(desc.dirs_count.get()))
+            .sum();
+        let around_parent = (0..count).map(|i| (parent_group + i) % count);
+
+        if parent == EXT2_ROOT_INO {
+            let best = around_parent
+                .clone()
+                .filter(|&group| {
+                    let desc = &self.groups()[group as usize];
+                    desc.free_inodes_count.get() > 0
+                        && u32::from(desc.free_inodes_count.get()) >= free_inodes
+                        && u32::from(desc.free_blocks_count.get()) >= free_blocks
+                })
+                .min_by_key(|&group| self.groups()[group as usize].dirs_count.get());
+            if best.is_some() {
+                return best;
+            }
+        } else {
+            let max_dirs = dirs / count + sb.inodes_per_group.get() / 16;
+            let min_inodes = free_inodes
+                .saturating_sub(sb.inodes_per_group.get() / 4)
+                .max(1);
+            let min_blocks = free_blocks.saturating_sub(sb.blocks_per_group.get() / 4);
+            let found = around_parent.clone().find(|&group| {
+                let desc = &self.groups()[group as usize];
+                u32::from(desc.dirs_count.get()) < max_dirs
+                    && u32::from(desc.free_inodes_count.get()) >= min_inodes
+                    && u32::from(desc.free_blocks_count.get()) >= min_blocks
+            });
+            if found.is_some() {
+                return found;
+            }
+        }
+
+        // Fall back to any group with an average share of free inodes, then to any at all.
+        let free_inodes_in =
+            |group: u32| u32::from(self.groups()[group as usize].free_inodes_count.get());
+        around_parent
+            .clone()
+            .find(|&group| free_inodes > 0 && free_inodes_in(group) >= free_inodes)
+            .or_else(|| {
+                around_parent
+                    .clone()
+                    .find(|&group| free_inodes_in(group) > 0)
+            })
+    }
+
+    /// Choose a group for a new non-directory.
+    ///
+    /// The parent's group is preferred. Otherwise groups are probed quadratically from a start
+    /// depending on the parent, to spread files from different directories, and finally
+    /// searched linearly.
+    fn find_group_other(&self, parent: InodeNumber, parent_group: u32) -> Option<u32> {
+        let count = self.group_count();
+        let desc = |group: u32| &self.groups()[group as usize];
+        let has_room = |group: u32| {
+            desc(group).free_inodes_count.get() > 0 && desc(group).free_blocks_count.get() > 0
+        };
+
+        if has_room(parent_group) {
+            return Some(parent_group);
+        }
+
+        let mut group = (parent_group + parent) % count;
+        let mut step = 1;
+        while step < count {
+            group = (group + step) % count;
+            if has_room(group) {
+                return Some(group);
+            }
+            step <<= 1;
+        }
+
+        (0..count)
+            .map(|i| (parent_group + i) % count)
+            .find(|&group| desc(group).free_inodes_count.get() > 0)
+    }
+
+    fn inode_bitmap(&self, group: u32) -> Result<Vec<u8>> {
+        let mut bits = vec![0; self.block_size()];
+        let block = self.groups()[group as usize].inode_usage_addr.get();
+        self.read_block_at(block, 0, &mut bits)?;
+        Ok(bits)
+    }
+
+    fn write_inode_bitmap(&mut self, group: u32, bits: &[u8]) -> Result<()> {
+        let block = self.groups()[group as usize].inode_usage_addr.get();
+        self.write_block_at(block, 0, bits)
+    }
+
+    /// Add `delta` to the free inode counts of `group` and the superblock, and subtract it from
+    /// the group's directory count if the inode is a directory.
+    fn adjust_free_inodes(&mut self, group: u32, delta: i16, directory: bool) -> Result<()> {
+        let mut desc = self.groups()[group as usize].clone();
+        let free = desc.free_inodes_count.get().wrapping_add_signed(delta);
+        desc.free_inodes_count.set(free);
+        if directory {
+            let dirs = desc.dirs_count.get().wrapping_add_signed(-delta);
+            desc.dirs_count.set(dirs);
+        }
+        self.write_group(group, desc)?;
+
+        let mut superblock = self.superblock().clone();
+        let free = superblock
+            .free_inodes_count
+            .get()
+            .wrapping_add_signed(i32::from(delta));
+        superblock.free_inodes_count.set(free);
+        self.write_superblock(superblock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+    use crate::mode::Permissions;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn allocates_files_near_their_parent() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+
+        assert_eq!(fs.allocate_inode(EXT2_ROOT_INO, false).unwrap(), 12);
+        assert_eq!(fs.allocate_inode(EXT2_ROOT_INO, false).unwrap(), 13);
+        assert_eq!(fs.allocate_inode(EXT2_ROOT_INO, false).unwrap(), 15);
+        assert_eq!(fs.allocate_inode(1281, false).unwrap(), 1282);
+
+        let fs = Filesystem::new(&image[..]).unwrap();
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546 - 4);
+        assert_eq!(fs.groups()[0].free_inodes_count.get(), 1268 - 3);
+        assert_eq!(fs.groups()[0].dirs_count.get(), 2);
+    }
+
+    #[test]
+    fn spreads_top_level_directories() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+
+        // Group 0 has fewer free inodes than average, so the new directory goes to group 1.
+        assert_eq!(fs.allocate_inode(EXT2_ROOT_INO, true).unwrap(), 1282);
+        assert_eq!(fs.groups()[1].dirs_count.get(), 2);
+        // Group 1 is short of blocks, so subdirectories of its directories go to group 0.
+        assert_eq!(fs.allocate_inode(1281, true).unwrap(), 12);
+        assert_eq!(fs.groups()[0].dirs_count.get(), 3);
+    }
+
+    #[test]
+    fn places_directories_when_fewer_inodes_than_groups_are_free() {
+        for parent in [EXT2_ROOT_INO, 1281] {
+            let mut image = IMAGE.to_vec();
+            let mut fs = Ext2::new(&mut image[..]).unwrap();
+            for _ in 0..2545 {
+                fs.allocate_inode(EXT2_ROOT_INO, false).unwrap();
+            }
+            assert_eq!(fs.groups()[0].free_inodes_count.get(), 0);
+            assert_eq!(fs.groups()[1].free_inodes_count.get(), 1);
+
+            let dir = fs
+                .mkdir(parent, "d", Permissions::from_bits_retain(0o755))
+                .unwrap();
+            assert_eq!((dir - 1) / fs.superblock().inodes_per_group.get(), 1);
+            assert_eq!(fs.allocate_inode(parent, true).unwrap_err(), Error::NoSpace);
+        }
+    }
+
+    #[test]
+    fn frees_inodes() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let number = fs.allocate_inode(EXT2_ROOT_INO, false).unwrap();
+        fs.free_inode(number, false).unwrap();
+
+        assert_eq!(
+            fs.free_inode(number, false).unwrap_err(),
+            Error::CorruptBitmap { group: 0 }
+        );
+        assert_eq!(fs.free_inode(7, false).unwrap_err(), Error::InvalidInode(7));
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546);
+        assert_eq!(fs.groups()[0].free_inodes_count.get(), 1268);
+    }
+
+    #[test]
+    fn frees_directories() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.free_inode(1281, true).unwrap();
+
+        assert_eq!(fs.groups()[1].dirs_count.get(), 0);
+        assert_eq!(fs.groups()[1].free_inodes_count.get(), 1279);
+    }
+}
@@ -0,0 +1,426 @@
+//! Read and write inodes in the inode tables.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::mode::FileMode;
+use crate::schema::{Inode, InodeExtra, InodeFlags, InodeNumber, EXT2_GOOD_OLD_INODE_SIZE};
+
+/// The size of the base inode, which every inode record starts with.
+const BASE_SIZE: usize = EXT2_GOOD_OLD_INODE_SIZE as usize;
+
+/// The flags [`Ext2::set_inode_flags`] may change; the others are maintained by the filesystem.
+const USER_MODIFIABLE: InodeFlags = InodeFlags::from_bits_retain(0x0003_80ff);
+
+const _: () = assert!(size_of::<Inode>() == BASE_SIZE);
+
+impl InodeExtra {
+    /// The nanoseconds part of the change time.
+    #[must_use]
+    pub fn ctime_nsec(&self) -> u32 {
+        self.ctime_extra.get() >> 2
+    }
+
+    /// The nanoseconds part of the modification time.
+    #[must_use]
+    pub fn mtime_nsec(&self) -> u32 {
+        self.mtime_extra.get() >> 2
+    }
+
+    /// The nanoseconds part of the access time.
+    #[must_use]
+    pub fn atime_nsec(&self) -> u32 {
+        self.atime_extra.get() >> 2
+    }
+
+    /// The nanoseconds part of the creation time.
+    #[must_use]
+    pub fn crtime_nsec(&self) -> u32 {
+        self.crtime_extra.get() >> 2
+    }
+}
+
+impl Inode {
+    /// The owner's user ID, including the upper 16 bits stored in `_os_specific_2`.
+    #[must_use]
+    pub fn owner_uid(&self) -> u32 {
+        let high = u16::from_le_bytes([self._os_specific_2[4], self._os_specific_2[5]]);
+        u32::from(high) << 16 | u32::from(self.uid.get())
+    }
+
+    /// The owner's group ID, including the upper 16 bits stored in `_os_specific_2`.
+    #[must_use]
+    pub fn owner_gid(&self) -> u32 {
+        let high = u16::from_le_bytes([self._os_specific_2[6], self._os_specific_2[7]]);
+        u32::from(high) << 16 | u32::from(self.gid.get())
+    }
+
+    /// Set the owner's user and group IDs.
+    pub fn set_owner(&mut self, uid: u32, gid: u32) {
+        self.uid.set(uid as u16);
+        self.gid.set(gid as u16);
+        self._os_specific_2[4..6].copy_from_slice(&((uid >> 16) as u16).to_le_bytes());
+        self._os_specific_2[6..8].copy_from_slice(&((gid >> 16) as u16).to_le_bytes());
+    }
+
+    /// The inode's flags. Bits this crate does not know are kept.
+    #[must_use]
+    pub fn inode_flags(&self) -> InodeFlags {
+        InodeFlags::from_bits_retain(self.flags.get())
+    }
+
+    /// Replace the inode's flags.
+    pub fn set_inode_flags(&mut self, flags: InodeFlags) {
+        self.flags.set(flags.bits());
+    }
+
+    /// Fail unless the contents and entries of inode number `number`, which is this inode, may
+    /// be changed.
+    pub(crate) fn check_mutable(&self, number: InodeNumber) -> Result<()> {
+        if self.inode_flags().contains(InodeFlags::IMMUTABLE) {
+            return Err(Error::NotPermitted(number));
+        }
+        Ok(())
+    }
+
+    /// Fail unless inode number `number`, which is this inode, may be removed, renamed, linked
+    /// to or truncated, or have its directory entries removed.
+    pub(crate) fn check_removable(&self, number: InodeNumber) -> Result<()> {
+        if self
+            .inode_flags()
+            .intersects(InodeFlags::IMMUTABLE | InodeFlags::APPEND)
+        {
+            return Err(Error::NotPermitted(number));
+        }
+        Ok(())
+    }
+}
+
+/// Check that the `extra_isize` of inode `number` fits in the `room` after the base inode.
+fn extra_isize(number: InodeNumber, extra: &InodeExtra, room: usize) -> Result<usize> {
+    let used = usize::from(extra.extra_isize.get());
+    if used > room || used % 4 != 0 {
+        return Err(Error::CorruptInode(number));
+    }
+    Ok(used)
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Read inode number `number`.
+    ///
+    /// Only the first 128 bytes of the inode are read; see [`Ext2::inode_extra`] for the rest.
+    ///
+    /// # Errors
+    /// If `number` is 0 or larger than the number of inodes in the filesystem, or the inode table
+    /// cannot be read.
+    pub fn inode(&self, number: InodeNumber) -> Result<Inode> {
+        let (block, offset) = self.inode_location(number)?;
+        let mut inode = Inode::new_zeroed();
+        self.read_block_at(block, offset, inode.as_bytes_mut())?;
+        Ok(inode)
+    }
+
+    /// Write the first 128 bytes of inode number `number`, leaving any extra fields untouched.
+    ///
+    /// # Errors
+    /// If `number` is not a valid inode number, or the inode table cannot be written.
+    pub fn write_inode(&mut self, number: InodeNumber, inode: &Inode) -> Result<()> {
+        let (block, offset) = self.inode_location(number)?;
+        self.write_block_at(block, offset, inode.as_bytes())
+    }
+
+    /// Set the flags of inode number `number`, like `chattr`, and update its change time.
+    ///
+    /// Only the flags a user may change are taken from `flags`; the others, such as
+    /// [`InodeFlags::INDEX`], keep their current values.
+    ///
+    /// # Errors
+    /// - [`Error::NotPermitted`] unless the filesystem's [`Credentials`](crate::Credentials) are
+    ///   the superuser or own the inode, and only the superuser may change
+    ///   [`InodeFlags::IMMUTABLE`] and [`InodeFlags::APPEND`].
+    /// - If the inode cannot be read or written.
+    pub fn set_inode_flags(&mut self, number: InodeNumber, flags: InodeFlags) -> Result<()> {
+        let mut inode = self.inode(number)?;
+        let old = inode.inode_flags();
+        let new = (old - USER_MODIFIABLE) | (flags & USER_MODIFIABLE);
+        let uid = self.credentials().uid;
+        let guarded = InodeFlags::IMMUTABLE | InodeFlags::APPEND;
+        if uid != 0 && (uid != inode.owner_uid() || (old ^ new).intersects(guarded)) {
+            return Err(Error::NotPermitted(number));
+        }
+
+        inode.set_inode_flags(new);
+        inode.ctime.set(self.now());
+        self.write_inode(number, &inode)
+    }
+
+    /// Read the fields stored after the first 128 bytes of inode number `number`.
+    ///
+    /// Returns `None` if the filesystem's inodes are only 128 bytes. Fields past `extra_isize`
+    /// are not stored on disk, and are returned as zero.
+    ///
+    /// # Errors
+    /// If `number` is not a valid inode number, the inode table cannot be read, or the inode's
+    /// `extra_isize` does not fit in the inode.
+    pub fn inode_extra(&self, number: InodeNumber) -> Result<Option<InodeExtra>> {
+        let (block, offset) = self.inode_location(number)?;
+        let room = self.superblock().inode_record_size() - BASE_SIZE;
+        if room == 0 {
+            return Ok(None);
+        }
+
+        let mut extra = InodeExtra::new_zeroed();
+        let len = room.min(size_of::<InodeExtra>());
+        self.read_block_at(block, offset + BASE_SIZE, &mut extra.as_bytes_mut()[..len])?;
+
+        let used = extra_isize(number, &extra, room)?;
+        extra.as_bytes_mut()[used.min(len)..].fill(0);
+        Ok(Some(extra))
+    }
+
+    /// Write the first `extra.extra_isize.get()` bytes of `extra` after the first 128 bytes of inode
+    /// number `number`.
+    ///
+    /// # Errors
+    /// If `number` is not a valid inode number, the filesystem's inodes have no room for extra
+    /// fields, `extra_isize` is too large, or the inode table cannot be written.
+    pub fn write_inode_extra(&mut self, number: InodeNumber, extra: &InodeExtra) -> Result<()> {
+        let (block, offset) = self.inode_location(number)?;
+        let room = self.superblock().inode_record_size() - BASE_SIZE;
+        let used = extra_isize(number, extra, room)?;
+
+        let len = used.min(size_of::<InodeExtra>());
+        self.write_block_at(block, offset + BASE_SIZE, &extra.as_bytes()[..len])
+    }
+
+    /// Read the space in inode number `number` after its extra fields, which holds in-inode
+    /// extended attributes.
+    ///
+    /// # Errors
+    /// If `number` is not a valid inode number, the inode table cannot be read, or the inode's
+    /// `extra_isize` does not fit in the inode.
+    pub fn inode_xattr_space(&self, number: InodeNumber) -> Result<Vec<u8>> {
+        let Some(extra) = self.inode_extra(number)? else {
+            return Ok(Vec::new());
+        };
+        let (block, offset) = self.inode_location(number)?;
+        let start = BASE_SIZE + usize::from(extra.extra_isize.get());
+
+        let mut space = vec![0; self.superblock().inode_record_size() - start];
+        self.read_block_at(block, offset + start, &mut space)?;
+        Ok(space)
+    }
+
+    /// Write `space` to the start of the space in inode number `number` after its extra fields.
+    ///
+    /// # Errors
+    /// - [`Error::NoSpace`] if `space` is longer than the space in the inode.
+    /// - If `number` is not a valid inode number, the inode table cannot be read or written, or
+    ///   the inode's `extra_isize` does not fit in the inode.
+    pub fn write_inode_xattr_space(&mut self, number: InodeNumber, space: &[u8]) -> Result<()> {
+        let extra = self.inode_extra(number)?;
+        let used = extra
+            .as_ref()
+            .map_or(0, |extra| usize::from(extra.extra_isize.get()));
+        let start = BASE_SIZE + used;
+        let room = self.superblock().inode_record_size().saturating_sub(start);
+        if extra.is_none() || space.len() > room {
+            return Err(Error::NoSpace);
+        }
+
+        let (block, offset) = self.inode_location(number)?;
+        self.write_block_at(block, offset + start, space)
+    }
+
+    /// A new inode with `mode`, owned by the filesystem's credentials, with one link and all
+    /// timestamps set to now.
+    pub(crate) fn new_inode(&self, mode: FileMode) -> Inode {
+        let now = self.now();
+        let mut inode = Inode::new_zeroed();
+        inode.type_perm.set(mode.to_raw());
+        inode.set_owner(self.credentials().uid, self.credentials().gid);
+        inode.atime.set(now);
+        inode.ctime.set(now);
+        inode.mtime.set(now);
+        inode.hard_links.set(1);
+        inode
+    }
+
+    /// Write a newly allocated inode, clearing whatever the record held before.
+    ///
+    /// In filesystems with large inodes, the extra fields are sized by `want_extra_isize` and
+    /// the creation time is set to the inode's change time.
+    pub(crate) fn init_inode(&mut self, number: InodeNumber, inode: &Inode) -> Result<()> {
+        let (block, offset) = self.inode_location(number)?;
+        let record = self.superblock().inode_record_size();
+        self.write_block_at(block, offset + BASE_SIZE, &vec![0; record - BASE_SIZE])?;
+        self.write_inode(number, inode)?;
+
+        let room = record - BASE_SIZE;
+        if room == 0 {
+            return Ok(());
+        }
+        let want = usize::from(self.superblock().want_extra_isize.get());
+        let used = if want == 0 || want > room || want % 4 != 0 {
+            room.min(size_of::<InodeExtra>())
+        } else {
+            want
+        };
+        let mut extra = InodeExtra::new_zeroed();
+        extra.extra_isize.set(used as u16);
+        extra.crtime.set(inode.ctime.get());
+        self.write_inode_extra(number, &extra)
+    }
+
+    /// Find the block group holding inode `number`.
+    pub(crate) fn inode_group(&self, number: InodeNumber) -> Result<u32> {
+        let sb = self.superblock();
+        if number == 0 || number > sb.inodes_count.get() {
+            return Err(Error::InvalidInode(number));
+        }
+        Ok((number - 1) / sb.inodes_per_group.get())
+    }
+
+    /// Find the block containing inode `number`, and the offset of the inode within that block.
+    fn inode_location(&self, number: InodeNumber) -> Result<(u32, usize)> {
+        let group = self.inode_group(number)?;
+        let sb = self.superblock();
+        let index = ((number - 1) % sb.inodes_per_group.get()) as usize;
+        let table = self
+            .group(group)
+            .ok_or(Error::InvalidInode(number))?
+            .inode_table_block
+            .get();
+
+        let byte = index * sb.inode_record_size();
+        let block = table + u32::try_from(byte / self.block_size()).expect("fits in a group");
+        Ok((block, byte % self.block_size()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{Credentials, Filesystem};
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn reads_root_inode() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let root = fs.inode(2).unwrap();
+
+        assert_eq!(root.type_perm.get(), 0o40755);
+        assert_eq!(root.hard_links.get(), 4);
+    }
+
+    #[test]
+    fn reads_inode_in_second_group() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let dir = fs.inode(1281).unwrap();
+
+        assert_eq!(dir.type_perm.get(), 0o40755);
+        assert_eq!(dir.size_low.get(), 1024);
+    }
+
+    #[test]
+    fn rejects_out_of_range_inodes() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.inode(0).unwrap_err(), Error::InvalidInode(0));
+        assert_eq!(fs.inode(2561).unwrap_err(), Error::InvalidInode(2561));
+    }
+
+    #[test]
+    fn reads_extra_fields() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let extra = fs.inode_extra(14).unwrap().unwrap();
+
+        assert_eq!(extra.extra_isize.get(), 32);
+        assert_eq!(extra.crtime.get(), 0x63f9_04fc);
+        assert_eq!(extra.mtime_nsec(), 0x56c8_cf98 >> 2);
+        assert_eq!(fs.inode_xattr_space(14).unwrap().len(), 256 - 128 - 32);
+    }
+
+    #[test]
+    fn writes_inode_without_touching_extra_fields() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+
+        let mut inode = fs.inode(14).unwrap();
+        inode.uid.set(1000);
+        fs.write_inode(14, &inode).unwrap();
+
+        assert_eq!(fs.inode(14).unwrap().uid.get(), 1000);
+        assert_eq!(
+            fs.inode_extra(14).unwrap().unwrap().crtime.get(),
+            0x63f9_04fc
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_extra_isize() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+
+        let mut extra = fs.inode_extra(14).unwrap().unwrap();
+        extra.extra_isize.set(132);
+        assert_eq!(
+            fs.write_inode_extra(14, &extra).unwrap_err(),
+            Error::CorruptInode(14)
+        );
+    }
+
+    #[test]
+    fn sets_only_user_modifiable_flags() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut inode = fs.inode(2).unwrap();
+        inode.set_inode_flags(InodeFlags::INDEX);
+        fs.write_inode(2, &inode).unwrap();
+
+        fs.set_inode_flags(2, InodeFlags::NODUMP | InodeFlags::JOURNAL_DATA)
+            .unwrap();
+        assert_eq!(
+            fs.inode(2).unwrap().inode_flags(),
+            InodeFlags::INDEX | InodeFlags::NODUMP
+        );
+    }
+
+    #[test]
+    fn reserves_immutable_and_append_only_for_root() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut inode = fs.inode(14).unwrap();
+        inode.set_owner(1000, 1000);
+        fs.write_inode(14, &inode).unwrap();
+
+        fs.set_credentials(Credentials {
+            uid: 1001,
+            gid: 1000,
+        });
+        assert_eq!(
+            fs.set_inode_flags(14, InodeFlags::NOATIME),
+            Err(Error::NotPermitted(14))
+        );
+        fs.set_credentials(Credentials {
+            uid: 1000,
+            gid: 1000,
+        });
+        fs.set_inode_flags(14, InodeFlags::NOATIME).unwrap();
+        assert_eq!(
+            fs.set_inode_flags(14, InodeFlags::IMMUTABLE),
+            Err(Error::NotPermitted(14))
+        );
+
+        fs.set_credentials(Credentials::ROOT);
+        fs.set_inode_flags(14, InodeFlags::IMMUTABLE).unwrap();
+        assert_eq!(fs.inode(14).unwrap().inode_flags(), InodeFlags::IMMUTABLE);
+    }
+}
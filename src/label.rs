@@ -0,0 +1,173 @@
+//! Read and change the superblock's identifiers and names.
+
+use core::str;
+
+use uuid::Uuid;
+
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::schema::Superblock;
+
+/// The bytes of a zero-padded string field before the first NUL.
+fn until_nul(field: &[u8]) -> &[u8] {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..end]
+}
+
+/// Store `value` in a zero-padded string field.
+///
+/// The field need not end in a NUL if `value` fills it.
+fn set_padded(field: &mut [u8], value: &[u8]) -> Result<()> {
+    if value.len() > field.len() || value.contains(&0) {
+        return Err(Error::InvalidLabel);
+    }
+    field.fill(0);
+    field[..value.len()].copy_from_slice(value);
+    Ok(())
+}
+
+impl Superblock {
+    /// The filesystem's UUID, as shown by `blkid`.
+    #[must_use]
+    pub const fn uuid(&self) -> Uuid {
+        Uuid::from_bytes(self.fs_id)
+    }
+
+    /// Set the filesystem's UUID.
+    pub fn set_uuid(&mut self, uuid: Uuid) {
+        self.fs_id = uuid.into_bytes();
+    }
+
+    /// The UUID of the external journal, or nil if the journal is internal.
+    #[must_use]
+    pub const fn journal_uuid(&self) -> Uuid {
+        Uuid::from_bytes(self.journal_id)
+    }
+
+    /// The raw volume label, without padding.
+    #[must_use]
+    pub fn volume_label_bytes(&self) -> &[u8] {
+        until_nul(&self.volume_name)
+    }
+
+    /// The volume label, or `None` if it is not valid UTF-8; see
+    /// [`Superblock::volume_label_bytes`].
+    #[must_use]
+    pub fn volume_label(&self) -> Option<&str> {
+        str::from_utf8(self.volume_label_bytes()).ok()
+    }
+
+    /// Set the volume label.
+    ///
+    /// # Errors
+    /// [`Error::InvalidLabel`] if `label` is longer than 16 bytes or contains a NUL.
+    pub fn set_volume_label(&mut self, label: impl AsRef<[u8]>) -> Result<()> {
+        set_padded(&mut self.volume_name, label.as_ref())
+    }
+
+    /// The raw path the filesystem was last mounted at, without padding.
+    #[must_use]
+    pub fn last_mounted_bytes(&self) -> &[u8] {
+        until_nul(&self.last_mnt_path)
+    }
+
+    /// The path the filesystem was last mounted at, or `None` if it is not valid UTF-8; see
+    /// [`Superblock::last_mounted_bytes`].
+    #[must_use]
+    pub fn last_mounted(&self) -> Option<&str> {
+        str::from_utf8(self.last_mounted_bytes()).ok()
+    }
+
+    /// Set the path the filesystem was last mounted at.
+    ///
+    /// # Errors
+    /// [`Error::InvalidLabel`] if `path` is longer than 64 bytes or contains a NUL.
+    pub fn set_last_mounted(&mut self, path: impl AsRef<[u8]>) -> Result<()> {
+        set_padded(&mut self.last_mnt_path, path.as_ref())
+    }
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Change the volume label and write the superblock back to the device.
+    ///
+    /// # Errors
+    /// If the label is invalid (see [`Superblock::set_volume_label`]) or the superblock cannot be
+    /// written.
+    pub fn set_volume_label(&mut self, label: impl AsRef<[u8]>) -> Result<()> {
+        let mut superblock = self.superblock().clone();
+        superblock.set_volume_label(label)?;
+        self.write_superblock(superblock)
+    }
+
+    /// Change the filesystem's UUID and write the superblock back to the device.
+    ///
+    /// # Errors
+    /// If the superblock cannot be written.
+    pub fn set_uuid(&mut self, uuid: Uuid) -> Result<()> {
+        let mut superblock = self.superblock().clone();
+        superblock.set_uuid(uuid);
+        self.write_superblock(superblock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+    use alloc::string::ToString;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn reads_identifiers() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let sb = fs.superblock();
+
+        assert_eq!(
+            sb.uuid().to_string(),
+            "f1ee9dc6-5035-4618-9a9a-970cb3d9fd8c"
+        );
+        assert!(sb.journal_uuid().is_nil());
+        assert_eq!(sb.volume_label(), Some(""));
+        assert_eq!(sb.last_mounted(), Some("/home/dylan/mount-point"));
+    }
+
+    #[test]
+    fn falls_back_to_bytes() {
+        let mut sb = Filesystem::new(IMAGE).unwrap().superblock().clone();
+        sb.set_volume_label(b"\xffbad").unwrap();
+
+        assert_eq!(sb.volume_label(), None);
+        assert_eq!(sb.volume_label_bytes(), b"\xffbad");
+    }
+
+    #[test]
+    fn validates_and_pads_labels() {
+        let mut sb = Filesystem::new(IMAGE).unwrap().superblock().clone();
+
+        sb.set_volume_label("exactly16bytes!!").unwrap();
+        assert_eq!(sb.volume_label(), Some("exactly16bytes!!"));
+        sb.set_volume_label("short").unwrap();
+        assert_eq!(&sb.volume_name, b"short\0\0\0\0\0\0\0\0\0\0\0");
+        assert_eq!(
+            sb.set_volume_label("seventeen bytes!!"),
+            Err(Error::InvalidLabel)
+        );
+        assert_eq!(sb.set_last_mounted("/a\0b"), Err(Error::InvalidLabel));
+        assert_eq!(sb.volume_label(), Some("short"));
+    }
+
+    #[test]
+    fn relabels_filesystem() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let uuid = Uuid::from_bytes([7; 16]);
+        fs.set_volume_label("backup").unwrap();
+        fs.set_uuid(uuid).unwrap();
+
+        let fs = Filesystem::new(&image[..]).unwrap();
+        assert_eq!(fs.superblock().volume_label(), Some("backup"));
+        assert_eq!(fs.superblock().uuid(), uuid);
+    }
+}
@@ -1,6 +1,55 @@
-#![allow(dead_code)]
+//! Read and write ext2 filesystems.
+//!
+//! The [`Ext2`] type is the entry point: open one on a [`BlockDevice`] holding the filesystem and
+//! use it to reach the on-disk structures, or to create and write files with [`FileMut`].
+//! [`Filesystem`] is a shorthand for a filesystem image held in memory. The raw layouts are in
+//! [`schema`], and the commonly used ones are re-exported here.
+//!
+//! The crate is `no_std`. The `std` feature adds a [`BlockDevice`] implementation for
+//! `std::fs::File`, and `std::io` implementations for [`File`] and [`FileMut`].
 #![no_std]
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+mod acl;
+mod balloc;
+mod bitmap;
+mod blocks;
+mod device;
+mod dir;
+mod error;
+mod features;
+mod file;
+mod fs;
+mod hash;
+mod htree;
+mod ialloc;
+mod inode;
+mod label;
+mod mode;
+mod namei;
 mod parse;
-mod schema;
+mod path;
+pub mod schema;
+mod symlink;
+mod write;
+mod xattr;
+
+pub use acl::{Access, Acl, AclEntry, AclTag, ACL_ACCESS, ACL_DEFAULT};
+pub use blocks::Blocks;
+pub use device::{BlockDevice, DeviceError};
+pub use dir::{Dir, DirEntry, DirIter};
+pub use error::{Error, Result};
+pub use features::{CompatFeatures, IncompatFeatures, MountMode, RoCompatFeatures};
+pub use file::File;
+pub use fs::{Clock, Credentials, Ext2, Filesystem};
+pub use hash::{HashVersion, NameHash};
+pub use mode::{FileMode, FileType, Permissions};
+pub use parse::ParseError;
+pub use schema::{
+    BlockGroupDescriptor, DirectoryEntry, Inode, InodeExtra, InodeFlags, InodeNumber, Superblock,
+    TypeIndicator, TypePerm, EXT2_MAGIC,
+};
+pub use uuid::Uuid;
+pub use write::FileMut;
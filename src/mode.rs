@@ -0,0 +1,306 @@
+//! Decode the type and permissions of an inode.
+
+use core::fmt;
+
+use bitflags::bitflags;
+
+use crate::schema::{Inode, TypeIndicator};
+
+/// The bits of `type_perm` holding the file type.
+pub const TYPE_MASK: u16 = 0xf000;
+
+/// The type of an inode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    /// FIFO
+    Fifo,
+    /// Character device
+    Character,
+    /// Directory
+    Directory,
+    /// Block device
+    Block,
+    /// Regular file
+    Regular,
+    /// Symbolic link
+    Symlink,
+    /// Unix socket
+    Socket,
+}
+
+impl FileType {
+    /// Decode the type field of a raw `type_perm`, ignoring the permission bits.
+    ///
+    /// Returns `None` if the type field does not hold a known type.
+    #[must_use]
+    pub const fn from_mode(mode: u16) -> Option<Self> {
+        Some(match mode & TYPE_MASK {
+            0x1000 => Self::Fifo,
+            0x2000 => Self::Character,
+            0x4000 => Self::Directory,
+            0x6000 => Self::Block,
+            0x8000 => Self::Regular,
+            0xA000 => Self::Symlink,
+            0xC000 => Self::Socket,
+            _ => return None,
+        })
+    }
+
+    /// The type field of a raw `type_perm` for this type.
+    #[must_use]
+    pub const fn to_mode(self) -> u16 {
+        match self {
+            Self::Fifo => 0x1000,
+            Self::Character => 0x2000,
+            Self::Directory => 0x4000,
+            Self::Block => 0x6000,
+            Self::Regular => 0x8000,
+            Self::Symlink => 0xA000,
+            Self::Socket => 0xC000,
+        }
+    }
+
+    /// The type indicator a directory entry pointing to this type should have.
+    #[must_use]
+    pub const fn indicator(self) -> TypeIndicator {
+        match self {
+            Self::Fifo => TypeIndicator::Fifo,
+            Self::Character => TypeIndicator::Character,
+            Self::Directory => TypeIndicator::Directory,
+            Self::Block => TypeIndicator::Block,
+            Self::Regular => TypeIndicator::Regular,
+            Self::Symlink => TypeIndicator::Symlink,
+            Self::Socket => TypeIndicator::Socket,
+        }
+    }
+
+    /// Whether a directory entry with type indicator `indicator` may point to this type.
+    ///
+    /// [`TypeIndicator::Unknown`] is consistent with every type, since filesystems without the
+    /// file type feature do not record types in directory entries.
+    #[must_use]
+    pub fn matches(self, indicator: TypeIndicator) -> bool {
+        indicator == TypeIndicator::Unknown || indicator == self.indicator()
+    }
+
+    /// The character `ls -l` uses for this type.
+    const fn symbol(self) -> char {
+        match self {
+            Self::Fifo => 'p',
+            Self::Character => 'c',
+            Self::Directory => 'd',
+            Self::Block => 'b',
+            Self::Regular => '-',
+            Self::Symlink => 'l',
+            Self::Socket => 's',
+        }
+    }
+}
+
+impl From<FileType> for TypeIndicator {
+    fn from(file_type: FileType) -> Self {
+        file_type.indicator()
+    }
+}
+
+bitflags! {
+    /// The permission bits of an inode.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Permissions: u16 {
+        /// Other—execute permission
+        const O_EXEC = 0o001;
+        /// Other—write permission
+        const O_WRITE = 0o002;
+        /// Other—read permission
+        const O_READ = 0o004;
+        /// Group—execute permission
+        const G_EXEC = 0o010;
+        /// Group—write permission
+        const G_WRITE = 0o020;
+        /// Group—read permission
+        const G_READ = 0o040;
+        /// User—execute permission
+        const U_EXEC = 0o100;
+        /// User—write permission
+        const U_WRITE = 0o200;
+        /// User—read permission
+        const U_READ = 0o400;
+        /// Sticky Bit
+        const STICKY = 0o1000;
+        /// Set group ID
+        const SET_GID = 0o2000;
+        /// Set user ID
+        const SET_UID = 0o4000;
+    }
+}
+
+impl fmt::Display for Permissions {
+    /// Format the permissions like `ls -l`, e.g. `rwxr-xr-x`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let triple = |read, write, exec, special, set: char, unset: char| {
+            [
+                if self.contains(read) { 'r' } else { '-' },
+                if self.contains(write) { 'w' } else { '-' },
+                match (self.contains(exec), self.contains(special)) {
+                    (true, true) => set,
+                    (false, true) => unset,
+                    (true, false) => 'x',
+                    (false, false) => '-',
+                },
+            ]
+        };
+        let chars = [
+            triple(
+                Self::U_READ,
+                Self::U_WRITE,
+                Self::U_EXEC,
+                Self::SET_UID,
+                's',
+                'S',
+            ),
+            triple(
+                Self::G_READ,
+                Self::G_WRITE,
+                Self::G_EXEC,
+                Self::SET_GID,
+                's',
+                'S',
+            ),
+            triple(
+                Self::O_READ,
+                Self::O_WRITE,
+                Self::O_EXEC,
+                Self::STICKY,
+                't',
+                'T',
+            ),
+        ];
+        chars.iter().flatten().try_for_each(|c| write!(f, "{c}"))
+    }
+}
+
+/// The type and permissions of an inode, decoded from its `type_perm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileMode {
+    /// The type of the inode.
+    pub file_type: FileType,
+    /// The permission bits of the inode.
+    pub permissions: Permissions,
+}
+
+impl FileMode {
+    /// Combine a file type and permissions.
+    #[must_use]
+    pub const fn new(file_type: FileType, permissions: Permissions) -> Self {
+        Self {
+            file_type,
+            permissions,
+        }
+    }
+
+    /// Decode a raw `type_perm`.
+    ///
+    /// Returns `None` if the type field does not hold a known type.
+    #[must_use]
+    pub const fn from_raw(raw: u16) -> Option<Self> {
+        match FileType::from_mode(raw) {
+            Some(file_type) => Some(Self::new(
+                file_type,
+                Permissions::from_bits_retain(raw & !TYPE_MASK),
+            )),
+            None => None,
+        }
+    }
+
+    /// Encode the mode as a raw `type_perm`.
+    #[must_use]
+    pub const fn to_raw(self) -> u16 {
+        self.file_type.to_mode() | self.permissions.bits()
+    }
+}
+
+impl fmt::Display for FileMode {
+    /// Format the mode like `ls -l`, e.g. `drwxr-xr-x`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file_type.symbol(), self.permissions)
+    }
+}
+
+impl Inode {
+    /// The inode's type and permissions, or `None` if its type field is invalid.
+    #[must_use]
+    pub fn mode(&self) -> Option<FileMode> {
+        FileMode::from_raw(self.type_perm.get())
+    }
+
+    /// The inode's type, or `None` if its type field is invalid.
+    #[must_use]
+    pub fn file_type(&self) -> Option<FileType> {
+        FileType::from_mode(self.type_perm.get())
+    }
+
+    /// Whether this is a regular file.
+    #[must_use]
+    pub fn is_file(&self) -> bool {
+        matches!(self.file_type(), Some(FileType::Regular))
+    }
+
+    /// Whether this is a directory.
+    #[must_use]
+    pub fn is_dir(&self) -> bool {
+        matches!(self.file_type(), Some(FileType::Directory))
+    }
+
+    /// Whether this is a symbolic link.
+    #[must_use]
+    pub fn is_symlink(&self) -> bool {
+        matches!(self.file_type(), Some(FileType::Symlink))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn decodes_type_separately_from_permissions() {
+        let socket = FileMode::from_raw(0o140_755).unwrap();
+        let symlink = FileMode::from_raw(0o120_777).unwrap();
+
+        assert_eq!(socket.file_type, FileType::Socket);
+        assert_eq!(symlink.file_type, FileType::Symlink);
+        assert_eq!(symlink.permissions, Permissions::from_bits_retain(0o777));
+        assert_eq!(FileMode::from_raw(0o070_000), None);
+    }
+
+    #[test]
+    fn round_trips_raw_modes() {
+        for raw in 0..=u16::MAX {
+            if let Some(mode) = FileMode::from_raw(raw) {
+                assert_eq!(mode.to_raw(), raw);
+            }
+        }
+    }
+
+    #[test]
+    fn formats_like_ls() {
+        let fmt = |raw| FileMode::from_raw(raw).unwrap().to_string();
+
+        assert_eq!(fmt(0o040_755), "drwxr-xr-x");
+        assert_eq!(fmt(0o100_644), "-rw-r--r--");
+        assert_eq!(fmt(0o104_755), "-rwsr-xr-x");
+        assert_eq!(fmt(0o102_644), "-rw-r-Sr--");
+        assert_eq!(fmt(0o041_777), "drwxrwxrwt");
+        assert_eq!(fmt(0o041_776), "drwxrwxrwT");
+        assert_eq!(fmt(0o120_777), "lrwxrwxrwx");
+    }
+
+    #[test]
+    fn checks_directory_entry_types() {
+        assert!(FileType::Directory.matches(TypeIndicator::Directory));
+        assert!(FileType::Socket.matches(TypeIndicator::Unknown));
+        assert!(!FileType::Regular.matches(TypeIndicator::Symlink));
+        assert_eq!(TypeIndicator::from(FileType::Block), TypeIndicator::Block);
+    }
+}
@@ -0,0 +1,591 @@
+//! Create, link, unlink and rename directory entries.
+
+use crate::device::BlockDevice;
+use crate::dir::{check_name, record_len};
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::mode::{FileMode, FileType, Permissions};
+use crate::schema::{Inode, InodeNumber, EXT2_LINK_MAX, EXT2_ROOT_INO};
+
+/// Whether `name` is `.` or `..`, which are managed by the filesystem itself.
+fn is_dot(name: &[u8]) -> bool {
+    matches!(name, b"." | b"..")
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Create a directory named `name` with `permissions` in the directory at inode number
+    /// `parent`, returning its inode number.
+    ///
+    /// The new directory holds `.` and `..`, and the parent gains a link for its `..`.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidName`] or [`Error::NameTooLong`] if `name` cannot be stored.
+    /// - [`Error::AlreadyExists`] if `parent` already has an entry named `name`.
+    /// - [`Error::NotADirectory`] if `parent` is not a directory.
+    /// - [`Error::TooManyLinks`] if `parent` cannot have another subdirectory.
+    /// - [`Error::NotPermitted`] if `parent` is immutable.
+    /// - [`Error::NoSpace`] if no inode or block is available.
+    /// - If the filesystem cannot be read or written.
+    pub fn mkdir(
+        &mut self,
+        parent: InodeNumber,
+        name: impl AsRef<[u8]>,
+        permissions: Permissions,
+    ) -> Result<InodeNumber> {
+        let name = name.as_ref();
+        check_name(name)?;
+        if self.inode(parent)?.hard_links.get() >= EXT2_LINK_MAX {
+            return Err(Error::TooManyLinks(parent));
+        }
+
+        let number = self.allocate_inode(parent, true)?;
+        let mut inode = self.new_inode(FileMode::new(FileType::Directory, permissions));
+        inode.hard_links.set(2);
+        let created = self
+            .init_inode(number, &inode)
+            .and_then(|()| self.fill_new_dir(number, parent, &mut inode))
+            .and_then(|()| self.add_entry(parent, name, number, FileType::Directory));
+        if let Err(err) = created {
+            // Nothing links to the directory yet, so undo its creation and report what stopped
+            // it. The inode is freed as a directory even if its block could not be, and without
+            // reading back a type which may not have been written, so the directory counts stay
+            // right.
+            let _ = self.free_blocks_from(&mut inode, 0);
+            let _ = self.free_inode(number, true);
+            return Err(err);
+        }
+
+        self.adjust_links(parent, 1)?;
+        Ok(number)
+    }
+
+    /// Remove the empty directory named `name` from the directory at inode number `parent`.
+    ///
+    /// # Errors
+    /// - [`Error::NotFound`] if `parent` has no entry named `name`.
+    /// - [`Error::NotADirectory`] if the entry is not a directory.
+    /// - [`Error::NotEmpty`] if the directory has entries other than `.` and `..`.
+    /// - [`Error::InvalidRename`] if `name` is `.` or `..`.
+    /// - [`Error::NotPermitted`] if the directory, or `parent`, is immutable or append-only.
+    /// - If the filesystem cannot be read or written.
+    pub fn rmdir(&mut self, parent: InodeNumber, name: impl AsRef<[u8]>) -> Result<()> {
+        let name = name.as_ref();
+        if is_dot(name) {
+            return Err(Error::InvalidRename);
+        }
+        let number = self.find_entry(parent, name)?.ok_or(Error::NotFound)?;
+        let mut inode = self.inode(number)?;
+        if !inode.is_dir() {
+            return Err(Error::NotADirectory(number));
+        }
+        inode.check_removable(number)?;
+        if !self.read_dir(number)?.is_empty()? {
+            return Err(Error::NotEmpty(number));
+        }
+
+        self.remove_entry(parent, name)?;
+        self.adjust_links(parent, -1)?;
+        self.release_inode(number, &mut inode)
+    }
+
+    /// Add an entry named `name` for the existing inode number `number` to the directory at
+    /// inode number `parent`, giving the inode another hard link.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidName`] or [`Error::NameTooLong`] if `name` cannot be stored.
+    /// - [`Error::AlreadyExists`] if `parent` already has an entry named `name`.
+    /// - [`Error::NotADirectory`] if `parent` is not a directory.
+    /// - [`Error::IsADirectory`] if `number` is a directory, which cannot be hard linked.
+    /// - [`Error::TooManyLinks`] if the inode already has the most links it can have.
+    /// - [`Error::NotPermitted`] if the inode is immutable or append-only, or `parent` is
+    ///   immutable.
+    /// - If the filesystem cannot be read or written.
+    pub fn link(
+        &mut self,
+        number: InodeNumber,
+        parent: InodeNumber,
+        name: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        let mut inode = self.inode(number)?;
+        if inode.is_dir() {
+            return Err(Error::IsADirectory(number));
+        }
+        inode.check_removable(number)?;
+        if inode.hard_links.get() >= EXT2_LINK_MAX {
+            return Err(Error::TooManyLinks(number));
+        }
+        let file_type = inode.file_type().ok_or(Error::CorruptInode(number))?;
+
+        self.add_entry(parent, name.as_ref(), number, file_type)?;
+        inode.hard_links.set(inode.hard_links.get() + 1);
+        inode.ctime.set(self.now());
+        self.write_inode(number, &inode)
+    }
+
+    /// Remove the entry named `name`, which must not be a directory, from the directory at inode
+    /// number `parent`.
+    ///
+    /// The inode loses a link, and it and its blocks are freed once it has none left.
+    ///
+    /// # Errors
+    /// - [`Error::NotFound`] if `parent` has no entry named `name`.
+    /// - [`Error::NotADirectory`] if `parent` is not a directory.
+    /// - [`Error::IsADirectory`] if the entry is a directory; see [`Ext2::rmdir`].
+    /// - [`Error::NotPermitted`] if the inode, or `parent`, is immutable or append-only.
+    /// - If the filesystem cannot be read or written.
+    pub fn unlink(&mut self, parent: InodeNumber, name: impl AsRef<[u8]>) -> Result<()> {
+        let name = name.as_ref();
+        let number = self.find_entry(parent, name)?.ok_or(Error::NotFound)?;
+        let mut inode = self.inode(number)?;
+        if inode.is_dir() {
+            return Err(Error::IsADirectory(number));
+        }
+        inode.check_removable(number)?;
+
+        self.remove_entry(parent, name)?;
+        self.drop_link(number, &mut inode)
+    }
+
+    /// Move the entry named `old_name` in the directory at inode number `old_parent` to
+    /// `new_name` in the directory at inode number `new_parent`.
+    ///
+    /// An existing entry named `new_name` is replaced, as long as both are directories or
+    /// neither is, and a replaced directory is empty. The replaced inode loses a link. Renaming
+    /// an entry over another link to the same inode does nothing.
+    ///
+    /// The new entry is written before the old one is removed, so if the rename fails part-way
+    /// the inode is still reachable under at least one of its names.
+    ///
+    /// # Errors
+    /// - [`Error::NotFound`] if `old_parent` has no entry named `old_name`.
+    /// - [`Error::InvalidName`] or [`Error::NameTooLong`] if `new_name` cannot be stored.
+    /// - [`Error::InvalidRename`] if either name is `.` or `..`, or a directory would be moved
+    ///   into itself or one of its subdirectories.
+    /// - [`Error::NotADirectory`] if either parent is not a directory, or a directory would
+    ///   replace something else.
+    /// - [`Error::IsADirectory`] if something other than a directory would replace one.
+    /// - [`Error::NotEmpty`] if a directory would replace a directory which is not empty.
+    /// - [`Error::TooManyLinks`] if `new_parent` cannot have another subdirectory.
+    /// - [`Error::NotPermitted`] if the moved or replaced inode, or `old_parent`, is immutable
+    ///   or append-only, or `new_parent` is immutable, or append-only when an entry is replaced.
+    /// - If the filesystem cannot be read or written.
+    pub fn rename(
+        &mut self,
+        old_parent: InodeNumber,
+        old_name: impl AsRef<[u8]>,
+        new_parent: InodeNumber,
+        new_name: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        let (old_name, new_name) = (old_name.as_ref(), new_name.as_ref());
+        if is_dot(old_name) || is_dot(new_name) {
+            return Err(Error::InvalidRename);
+        }
+        check_name(new_name)?;
+        let source = self
+            .find_entry(old_parent, old_name)?
+            .ok_or(Error::NotFound)?;
+        let source_inode = self.inode(source)?;
+        source_inode.check_removable(source)?;
+        // The old entry is removed after the new one is written, so check it can be up front.
+        self.inode(old_parent)?.check_removable(old_parent)?;
+        let file_type = source_inode
+            .file_type()
+            .ok_or(Error::CorruptInode(source))?;
+        let is_dir = source_inode.is_dir();
+        let target = self.find_entry(new_parent, new_name)?;
+        if target == Some(source) {
+            return Ok(());
+        }
+        let moves_dir = is_dir && old_parent != new_parent;
+        if moves_dir {
+            self.check_not_ancestor(source, new_parent)?;
+        }
+
+        let mut replaced = None;
+        if let Some(target) = target {
+            let target_inode = self.inode(target)?;
+            target_inode.check_removable(target)?;
+            match (is_dir, target_inode.is_dir()) {
+                (true, false) => return Err(Error::NotADirectory(target)),
+                (false, true) => return Err(Error::IsADirectory(target)),
+                (true, true) if !self.read_dir(target)?.is_empty()? => {
+                    return Err(Error::NotEmpty(target))
+                }
+                _ => {}
+            }
+            self.replace_entry(new_parent, new_name, source, file_type)?;
+            replaced = Some((target, target_inode));
+        } else {
+            if moves_dir && self.inode(new_parent)?.hard_links.get() >= EXT2_LINK_MAX {
+                return Err(Error::TooManyLinks(new_parent));
+            }
+            self.add_entry(new_parent, new_name, source, file_type)?;
+        }
+        self.remove_entry(old_parent, old_name)?;
+
+        if moves_dir {
+            self.replace_entry(source, b"..", new_parent, FileType::Directory)?;
+            self.adjust_links(old_parent, -1)?;
+            self.adjust_links(new_parent, 1)?;
+        }
+        let mut source_inode = self.inode(source)?;
+        source_inode.ctime.set(self.now());
+        self.write_inode(source, &source_inode)?;
+
+        match replaced {
+            Some((target, mut target_inode)) if target_inode.is_dir() => {
+                // The replaced directory's `..` no longer links to the new parent.
+                self.adjust_links(new_parent, -1)?;
+                self.release_inode(target, &mut target_inode)
+            }
+            Some((target, mut target_inode)) => self.drop_link(target, &mut target_inode),
+            None => Ok(()),
+        }
+    }
+
+    /// Give the new directory `number` its first block, holding `.` and `..` entries pointing to
+    /// itself and `parent`, and write its inode.
+    fn fill_new_dir(
+        &mut self,
+        number: InodeNumber,
+        parent: InodeNumber,
+        inode: &mut Inode,
+    ) -> Result<()> {
+        let block_size = self.block_size();
+        let (block, _) = self.map_or_allocate(number, inode, 0, None)?;
+        let dot_len = record_len(1);
+        let indicator = FileType::Directory.indicator();
+        let mut data = self.entry_bytes(b".", number, indicator, dot_len);
+        data.resize(dot_len, 0);
+        data.extend(self.entry_bytes(b"..", parent, indicator, block_size - dot_len));
+        data.resize(block_size, 0);
+        self.write_block_at(block, 0, &data)?;
+
+        inode.set_size(block_size as u64);
+        self.write_inode(number, inode)
+    }
+
+    /// Check that `dir` is not `descendant` or one of its ancestors, by walking up the `..`
+    /// entries from `descendant` to the root.
+    fn check_not_ancestor(&self, dir: InodeNumber, descendant: InodeNumber) -> Result<()> {
+        let mut current = descendant;
+        // A corrupt filesystem could have a loop of `..` entries.
+        for _ in 0..self.superblock().inodes_count.get() {
+            if current == dir {
+                return Err(Error::InvalidRename);
+            }
+            if current == EXT2_ROOT_INO {
+                return Ok(());
+            }
+            current = self.find_entry(current, b"..")?.ok_or(Error::NotFound)?;
+        }
+        Err(Error::CorruptInode(descendant))
+    }
+
+    /// Add `delta` to the link count of inode number `number`, and update its change time.
+    fn adjust_links(&mut self, number: InodeNumber, delta: i16) -> Result<()> {
+        let mut inode = self.inode(number)?;
+        let links = inode.hard_links.get().saturating_add_signed(delta);
+        inode.hard_links.set(links);
+        inode.ctime.set(self.now());
+        self.write_inode(number, &inode)
+    }
+
+    /// Remove a link from the non-directory inode number `number`, freeing it once it has none.
+    fn drop_link(&mut self, number: InodeNumber, inode: &mut Inode) -> Result<()> {
+        let links = inode.hard_links.get().saturating_sub(1);
+        if links == 0 {
+            return self.release_inode(number, inode);
+        }
+        inode.hard_links.set(links);
+        inode.ctime.set(self.now());
+        self.write_inode(number, inode)
+    }
+
+    /// Free inode number `number`, which has no links left, along with its blocks and its
+    /// share of its extended attribute block.
+    ///
+    /// Only regular files, directories and slow symlinks have blocks, as in Linux: device inodes
+    /// keep their device number in the block pointers, and fast symlinks their target.
+    ///
+    /// The inode keeps its type, so that tools can still tell what it was, and records its
+    /// deletion time.
+    fn release_inode(&mut self, number: InodeNumber, inode: &mut Inode) -> Result<()> {
+        let has_blocks = inode.is_file()
+            || inode.is_dir()
+            || (inode.is_symlink() && !inode.is_fast_symlink(self.block_size()));
+        if has_blocks {
+            self.free_blocks_from(inode, 0)?;
+        }
+        self.release_xattr_block(number, inode)?;
+        let now = self.now();
+        inode.hard_links.set(0);
+        inode.ctime.set(now);
+        inode.dtime.set(now);
+        self.write_inode(number, inode)?;
+        self.free_inode(number, inode.is_dir())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+    use crate::schema::InodeFlags;
+    use alloc::vec::Vec;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+    const RWXR_XR_X: Permissions = Permissions::from_bits_retain(0o755);
+
+    fn names<D: BlockDevice>(fs: &Ext2<D>, dir: InodeNumber) -> Vec<Vec<u8>> {
+        let dir = fs.read_dir(dir).unwrap();
+        dir.iter()
+            .map(|entry| entry.unwrap().name.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn makes_directories() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let root_links = fs.inode(EXT2_ROOT_INO).unwrap().hard_links.get();
+        let number = fs.mkdir(EXT2_ROOT_INO, "new", RWXR_XR_X).unwrap();
+        let nested = fs.mkdir(number, "nested", RWXR_XR_X).unwrap();
+
+        let fs = Filesystem::new(&image[..]).unwrap();
+        assert_eq!(fs.lookup("/new/nested/../..").unwrap(), EXT2_ROOT_INO);
+        assert_eq!(fs.lookup("/new/nested/.").unwrap(), nested);
+        assert_eq!(names(&fs, nested), [&b"."[..], b".."]);
+        let inode = fs.inode(number).unwrap();
+        assert!(inode.is_dir());
+        assert_eq!(inode.hard_links.get(), 3);
+        assert_eq!(inode.size(), 1024);
+        assert_eq!(inode.sectors_count.get(), 2);
+        assert_eq!(
+            fs.inode(EXT2_ROOT_INO).unwrap().hard_links.get(),
+            root_links + 1
+        );
+        let dirs: u16 = fs.groups().iter().map(|g| g.dirs_count.get()).sum();
+        assert_eq!(dirs, 5);
+    }
+
+    #[test]
+    fn undoes_a_failed_mkdir() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+
+        assert_eq!(
+            fs.mkdir(EXT2_ROOT_INO, "test_directory", RWXR_XR_X),
+            Err(Error::AlreadyExists)
+        );
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496);
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546);
+        let dirs: u16 = fs.groups().iter().map(|g| g.dirs_count.get()).sum();
+        assert_eq!(dirs, 3);
+    }
+
+    #[test]
+    fn removes_empty_directories() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let root_links = fs.inode(EXT2_ROOT_INO).unwrap().hard_links.get();
+        fs.mkdir(EXT2_ROOT_INO, "new", RWXR_XR_X).unwrap();
+        fs.rmdir(EXT2_ROOT_INO, "new").unwrap();
+
+        assert_eq!(fs.lookup("/new").unwrap_err(), Error::NotFound);
+        assert_eq!(
+            fs.inode(EXT2_ROOT_INO).unwrap().hard_links.get(),
+            root_links
+        );
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496);
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546);
+        assert_eq!(
+            fs.rmdir(EXT2_ROOT_INO, "test_directory").unwrap_err(),
+            Error::NotEmpty(1281)
+        );
+        assert_eq!(
+            fs.rmdir(EXT2_ROOT_INO, "hello.txt").unwrap_err(),
+            Error::NotADirectory(14)
+        );
+        assert_eq!(
+            fs.rmdir(EXT2_ROOT_INO, "..").unwrap_err(),
+            Error::InvalidRename
+        );
+    }
+
+    #[test]
+    fn links_and_unlinks_files() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.link(14, 1281, "again.txt").unwrap();
+        assert_eq!(fs.inode(14).unwrap().hard_links.get(), 2);
+        fs.unlink(EXT2_ROOT_INO, "hello.txt").unwrap();
+
+        assert_eq!(fs.lookup("/test_directory/again.txt").unwrap(), 14);
+        assert_eq!(fs.inode(14).unwrap().hard_links.get(), 1);
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496);
+
+        fs.unlink(1281, "again.txt").unwrap();
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496 + 1);
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546 + 1);
+        assert_eq!(fs.inode(14).unwrap().hard_links.get(), 0);
+        assert_eq!(
+            fs.unlink(EXT2_ROOT_INO, "test_directory").unwrap_err(),
+            Error::IsADirectory(1281)
+        );
+        assert_eq!(
+            fs.link(1281, 2, "x").unwrap_err(),
+            Error::IsADirectory(1281)
+        );
+    }
+
+    #[test]
+    fn unlinks_device_nodes_without_freeing_blocks() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let number = fs.allocate_inode(EXT2_ROOT_INO, false).unwrap();
+        let mode = FileMode::new(FileType::Character, Permissions::from_bits_retain(0o660));
+        let mut inode = fs.new_inode(mode);
+        // /dev/sda, device 8:0, in the old encoding.
+        inode.direct_pointer[0].set(0x0800);
+        fs.init_inode(number, &inode).unwrap();
+        fs.add_entry(EXT2_ROOT_INO, b"sda", number, FileType::Character)
+            .unwrap();
+        fs.unlink(EXT2_ROOT_INO, "sda").unwrap();
+
+        assert_eq!(fs.lookup("/sda").unwrap_err(), Error::NotFound);
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496);
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546);
+    }
+
+    #[test]
+    fn unlinking_merges_records() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let size = fs.inode(EXT2_ROOT_INO).unwrap().size();
+        fs.unlink(EXT2_ROOT_INO, "hello.txt").unwrap();
+
+        assert_eq!(
+            names(&fs, EXT2_ROOT_INO),
+            [&b"."[..], b"..", b"lost+found", b"test_directory"]
+        );
+        let dir = fs.read_dir(EXT2_ROOT_INO).unwrap();
+        let slot = dir.find_slot(record_len(200)).unwrap().unwrap();
+        assert_eq!(
+            dir.locate(b"test_directory").unwrap().unwrap().offset,
+            slot.offset
+        );
+        fs.link(1284, EXT2_ROOT_INO, [b'x'; 200]).unwrap();
+        assert_eq!(fs.inode(EXT2_ROOT_INO).unwrap().size(), size);
+    }
+
+    #[test]
+    fn renames_files() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.rename(EXT2_ROOT_INO, "hello.txt", EXT2_ROOT_INO, "renamed.txt")
+            .unwrap();
+        fs.rename(EXT2_ROOT_INO, "renamed.txt", 1281, "file_in_folder.txt")
+            .unwrap();
+
+        assert_eq!(fs.lookup("/hello.txt").unwrap_err(), Error::NotFound);
+        assert_eq!(fs.lookup("/test_directory/file_in_folder.txt").unwrap(), 14);
+        // The replaced file had a single link, so it was freed.
+        assert_eq!(fs.inode(1284).unwrap().hard_links.get(), 0);
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546 + 1);
+    }
+
+    #[test]
+    fn moves_directories() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let root_links = fs.inode(EXT2_ROOT_INO).unwrap().hard_links.get();
+        let lost_links = fs.inode(11).unwrap().hard_links.get();
+        fs.rename(EXT2_ROOT_INO, "test_directory", 11, "moved")
+            .unwrap();
+
+        assert_eq!(fs.lookup("/lost+found/moved/..").unwrap(), 11);
+        assert_eq!(
+            fs.inode(EXT2_ROOT_INO).unwrap().hard_links.get(),
+            root_links - 1
+        );
+        assert_eq!(fs.inode(11).unwrap().hard_links.get(), lost_links + 1);
+    }
+
+    #[test]
+    fn refuses_invalid_renames() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let empty = fs.mkdir(1281, "empty", RWXR_XR_X).unwrap();
+
+        assert_eq!(
+            fs.rename(EXT2_ROOT_INO, "test_directory", empty, "inside")
+                .unwrap_err(),
+            Error::InvalidRename
+        );
+        assert_eq!(
+            fs.rename(EXT2_ROOT_INO, "lost+found", EXT2_ROOT_INO, "test_directory")
+                .unwrap_err(),
+            Error::NotEmpty(1281)
+        );
+        assert_eq!(
+            fs.rename(EXT2_ROOT_INO, "hello.txt", 1281, "empty")
+                .unwrap_err(),
+            Error::IsADirectory(empty)
+        );
+        assert_eq!(
+            fs.rename(1281, "empty", EXT2_ROOT_INO, "hello.txt")
+                .unwrap_err(),
+            Error::NotADirectory(14)
+        );
+        assert_eq!(
+            fs.rename(EXT2_ROOT_INO, "hello.txt", EXT2_ROOT_INO, "hello.txt"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn replaces_empty_directories() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let root_links = fs.inode(EXT2_ROOT_INO).unwrap().hard_links.get();
+        fs.mkdir(EXT2_ROOT_INO, "empty", RWXR_XR_X).unwrap();
+        fs.rename(EXT2_ROOT_INO, "test_directory", EXT2_ROOT_INO, "empty")
+            .unwrap();
+
+        assert_eq!(fs.lookup("/empty/file_in_folder.txt").unwrap(), 1284);
+        assert_eq!(
+            fs.inode(EXT2_ROOT_INO).unwrap().hard_links.get(),
+            root_links
+        );
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546);
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496);
+    }
+
+    #[test]
+    fn refuses_to_remove_protected_entries() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.set_inode_flags(14, InodeFlags::IMMUTABLE).unwrap();
+        assert_eq!(
+            fs.unlink(EXT2_ROOT_INO, "hello.txt"),
+            Err(Error::NotPermitted(14))
+        );
+        assert_eq!(
+            fs.rename(EXT2_ROOT_INO, "hello.txt", EXT2_ROOT_INO, "moved.txt"),
+            Err(Error::NotPermitted(14))
+        );
+        assert_eq!(
+            fs.link(14, EXT2_ROOT_INO, "again.txt"),
+            Err(Error::NotPermitted(14))
+        );
+
+        // Entries can be added to an append-only directory, but not removed from it.
+        fs.set_inode_flags(14, InodeFlags::empty()).unwrap();
+        fs.set_inode_flags(1281, InodeFlags::APPEND).unwrap();
+        fs.link(14, 1281, "hello.txt").unwrap();
+        assert_eq!(fs.unlink(1281, "hello.txt"), Err(Error::NotPermitted(1281)));
+        assert!(fs.lookup("/test_directory/hello.txt").is_ok());
+        assert!(fs.lookup("/hello.txt").is_ok());
+    }
+}
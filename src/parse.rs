@@ -1,79 +1,248 @@
 //! Parse an ext2 filesystem.
 
-use crate::schema::{Superblock, EXT2_MAGIC};
+use core::fmt;
+use core::mem::size_of;
+
+use zerocopy::{FromBytes, LayoutVerified};
+
+use crate::schema::{
+    InodeNumber, Superblock, EXT2_DYNAMIC_REV, EXT2_GOOD_OLD_FIRST_INO, EXT2_GOOD_OLD_INODE_SIZE,
+    EXT2_GOOD_OLD_REV, EXT2_MAGIC, EXT2_MAX_LOG_BLOCK_SIZE,
+};
+
+/// The reasons a superblock can be rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The buffer is shorter than a superblock.
+    TooShort {
+        /// The length of the buffer that was provided.
+        len: usize,
+    },
+    /// The magic number is not [`EXT2_MAGIC`].
+    BadMagic(u16),
+    /// The major revision is newer than this crate understands.
+    UnsupportedRevision(u32),
+    /// The `log_block_size` field describes a block size larger than 64 KiB.
+    BadBlockSize(u32),
+    /// The inode size is not a power of two between 128 bytes and the block size.
+    BadInodeSize(u16),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort { len } => write!(
+                f,
+                "superblock needs {} bytes, but only {len} were provided",
+                size_of::<Superblock>()
+            ),
+            Self::BadMagic(magic) => write!(f, "bad magic number {magic:#06x}"),
+            Self::UnsupportedRevision(rev) => write!(f, "unsupported revision {rev}"),
+            Self::BadBlockSize(log) => write!(f, "invalid block size 1024 << {log}"),
+            Self::BadInodeSize(size) => write!(f, "invalid inode size {size}"),
+        }
+    }
+}
 
 impl Superblock {
-    /// Load the superblock from an address.
+    /// Parse and validate a superblock from the start of a slice of bytes.
     ///
-    /// # Safety
-    /// The pointer must be to a valid superblock. Additionally, it must maintain Rust guarantees,
-    /// i.e.
-    /// 1. is not mutably borrowed for `'a`
-    /// 2. lives for `'a`
-    /// 3. is non-null, non-dangled, properly aligned
-    #[must_use]
-    pub unsafe fn from_addr<'a>(addr: *const usize) -> &'a Self {
-        let ptr = addr.cast::<Self>();
-        let ret = unsafe { &*ptr };
-        debug_assert_eq!(ret.magic, EXT2_MAGIC);
-        ret
+    /// This copies the superblock out of `bytes`, so `bytes` may have any alignment.
+    ///
+    /// # Errors
+    /// If `bytes` is too short or does not hold a superblock this crate can read.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        Self::check_len(bytes)?;
+        let sb = Self::read_from_prefix(bytes).expect("length was checked");
+        sb.validate()?;
+        Ok(sb)
     }
 
-    /// Load the superblock mutably from an address.
+    /// Load and validate the superblock from a slice of bytes, without copying.
     ///
-    /// # Safety
-    /// The pointer must be to a valid superblock. Additionally, it must maintain Rust guarantees,
-    /// i.e.
-    /// 1. is not otherwise borrowed for `'a`
-    /// 2. lives for `'a`
-    /// 3. is non-null, non-dangled, properly aligned
+    /// The superblock's fields are little-endian byte arrays, so `bytes` may have any alignment.
+    ///
+    /// # Errors
+    /// If `bytes` is too short or does not hold a superblock this crate can read.
+    pub fn from_bytes(bytes: &[u8]) -> Result<&Self, ParseError> {
+        Self::check_len(bytes)?;
+        let (sb, _) = LayoutVerified::<_, Self>::new_unaligned_from_prefix(bytes)
+            .expect("length was checked");
+        let sb = sb.into_ref();
+        sb.validate()?;
+        Ok(sb)
+    }
+
+    /// Load and validate the superblock mutably from a slice of bytes, without copying.
+    ///
+    /// # Errors
+    /// If `bytes` is too short or does not hold a superblock this crate can read.
+    pub fn from_bytes_mut(bytes: &mut [u8]) -> Result<&mut Self, ParseError> {
+        Self::check_len(bytes)?;
+        let (sb, _) = LayoutVerified::<_, Self>::new_unaligned_from_prefix(bytes)
+            .expect("length was checked");
+        let sb = sb.into_mut();
+        sb.validate()?;
+        Ok(sb)
+    }
+
+    /// Check that the fields this crate relies on hold sensible values.
+    ///
+    /// # Errors
+    /// With the first problem found.
+    pub fn validate(&self) -> Result<(), ParseError> {
+        if self.magic.get() != EXT2_MAGIC {
+            return Err(ParseError::BadMagic(self.magic.get()));
+        }
+        if self.rev_major.get() > EXT2_DYNAMIC_REV {
+            return Err(ParseError::UnsupportedRevision(self.rev_major.get()));
+        }
+        if self.log_block_size.get() > EXT2_MAX_LOG_BLOCK_SIZE {
+            return Err(ParseError::BadBlockSize(self.log_block_size.get()));
+        }
+        let inode_size = self.inode_record_size();
+        if !inode_size.is_power_of_two()
+            || inode_size < usize::from(EXT2_GOOD_OLD_INODE_SIZE)
+            || inode_size > self.block_size()
+        {
+            return Err(ParseError::BadInodeSize(self.inode_size.get()));
+        }
+        Ok(())
+    }
+
+    /// The size of a block, in bytes.
     #[must_use]
-    pub unsafe fn from_addr_mut<'a>(addr: *mut usize) -> &'a mut Self {
-        let ptr = addr.cast::<Self>();
-        let ret = unsafe { &mut *ptr };
-        debug_assert_eq!(ret.magic, EXT2_MAGIC);
-        ret
+    pub fn block_size(&self) -> usize {
+        1024 << self.log_block_size.get()
     }
 
-    /// Load the superblock from a slice of bytes.
+    /// The size of each record in the inode tables, in bytes.
     ///
-    /// # Safety
-    /// The bytes must represent a valid superblock. In particular, they must be non-null,
-    /// non-dangled, and properly aligned.
+    /// Revision 0 filesystems always use 128-byte inodes, and leave `inode_size` unset.
     #[must_use]
-    pub unsafe fn from_bytes(bytes: &[u8]) -> &Self {
-        let addr = bytes.as_ptr().cast();
-        // Safety: lifetimes and aliasing are enforced by the borrow checker. Other guarantees are
-        // mainted by the caller.
-        unsafe { Self::from_addr(addr) }
+    pub fn inode_record_size(&self) -> usize {
+        if self.rev_major.get() == EXT2_GOOD_OLD_REV {
+            usize::from(EXT2_GOOD_OLD_INODE_SIZE)
+        } else {
+            usize::from(self.inode_size.get())
+        }
     }
 
-    /// Load the superblock from a mutable slice of bytes.
+    /// The first inode number which is not reserved, and so may be allocated.
     ///
-    /// # Safety
-    /// The bytes must represent a valid superblock. In particular, they must be non-null,
-    /// non-dangled, and properly aligned.
+    /// Revision 0 filesystems always reserve inodes 1 to 10, and leave `first_inode` unset.
     #[must_use]
-    pub unsafe fn from_bytes_mut(bytes: &mut [u8]) -> &mut Self {
-        let addr = bytes.as_mut_ptr().cast();
-        // Safety: lifetimes and aliasing are enforced by the borrow checker. Other guarantees are
-        // mainted by the caller.
-        unsafe { Self::from_addr_mut(addr) }
+    pub fn first_usable_inode(&self) -> InodeNumber {
+        if self.rev_major.get() == EXT2_GOOD_OLD_REV {
+            EXT2_GOOD_OLD_FIRST_INO
+        } else {
+            self.first_inode.get()
+        }
+    }
+
+    fn check_len(bytes: &[u8]) -> Result<(), ParseError> {
+        if bytes.len() < size_of::<Self>() {
+            return Err(ParseError::TooShort { len: bytes.len() });
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::vec;
+    use zerocopy::AsBytes;
+
+    const BYTES: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/resources/test-superblock"
+    ));
 
     #[test]
     fn superblock_from_bytes_works() {
-        let bytes = include_bytes!(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/tests/resources/test-superblock"
-        ));
-        let sb = unsafe { Superblock::from_bytes(bytes) };
+        let sb = Superblock::parse(BYTES).unwrap();
+
+        assert_eq!(sb.magic.get(), EXT2_MAGIC);
+        assert_eq!(sb.block_size(), 1024);
+    }
+
+    #[test]
+    fn superblock_decodes_little_endian_fields() {
+        let sb = Superblock::parse(BYTES).unwrap();
+
+        // Each multi-byte field is decoded from little-endian bytes, whatever the host's byte
+        // order, so these hold on big-endian targets too.
+        assert_eq!(sb.inodes_count.get(), 2560);
+        assert_eq!(sb.blocks_count.get(), 10240);
+        assert_eq!(sb.inode_size.get(), 256);
+        assert_eq!(sb.max_mnt_count.get(), -1);
+        assert_eq!(sb.inodes_count.as_bytes(), 2560u32.to_le_bytes());
+        assert_eq!(sb.as_bytes(), BYTES);
+    }
+
+    #[test]
+    fn superblock_reads_extended_fields() {
+        let sb = Superblock::parse(BYTES).unwrap();
+
+        assert_eq!(sb.reserved_gdt_blocks.get(), 39);
+        assert_eq!(sb.def_hash_version, 1);
+        assert_eq!(sb.hash_seed[0].as_bytes(), [0xbb, 0x50, 0xec, 0x40]);
+        assert_eq!(sb.mkfs_time.get(), 1_677_263_980);
+        assert_eq!(sb.min_extra_isize.get(), 32);
+        assert_eq!(sb.want_extra_isize.get(), 32);
+    }
+
+    #[test]
+    fn superblock_rejects_short_buffer() {
+        assert_eq!(
+            Superblock::parse(&BYTES[..100]).unwrap_err(),
+            ParseError::TooShort { len: 100 }
+        );
+    }
+
+    #[test]
+    fn superblock_rejects_bad_magic() {
+        let mut bytes = BYTES.to_vec();
+        bytes[56] = 0;
+        assert_eq!(
+            Superblock::parse(&bytes).unwrap_err(),
+            ParseError::BadMagic(0xef00)
+        );
+    }
+
+    #[test]
+    fn superblock_rejects_bad_block_size() {
+        let mut bytes = BYTES.to_vec();
+        bytes[24] = 7;
+        assert_eq!(
+            Superblock::parse(&bytes).unwrap_err(),
+            ParseError::BadBlockSize(7)
+        );
+    }
+
+    #[test]
+    fn superblock_rejects_bad_inode_size() {
+        let mut bytes = BYTES.to_vec();
+        bytes[88] = 200;
+        assert_eq!(
+            Superblock::parse(&bytes).unwrap_err(),
+            ParseError::BadInodeSize(456)
+        );
+    }
+
+    #[test]
+    fn superblock_from_bytes_accepts_any_alignment() {
+        let mut buf = vec![0; BYTES.len() + 3];
+        buf[3..].copy_from_slice(BYTES);
 
-        assert_eq!(sb.magic, EXT2_MAGIC);
+        let sb = Superblock::from_bytes(&buf[3..]).unwrap();
+        assert_eq!(sb.inodes_count.get(), 2560);
+        assert_eq!(
+            Superblock::from_bytes(&buf[4..]).unwrap_err(),
+            ParseError::TooShort {
+                len: BYTES.len() - 1
+            }
+        );
     }
 }
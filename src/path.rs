@@ -0,0 +1,182 @@
+//! Resolve paths to inodes.
+
+use alloc::vec::Vec;
+
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::schema::{InodeNumber, EXT2_NAME_LEN, EXT2_ROOT_INO};
+
+/// The most symbolic links followed while resolving a single path.
+pub const MAX_SYMLINKS: usize = 40;
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Find the inode at `path`, starting from the root directory.
+    ///
+    /// Empty components are ignored, so leading, trailing and repeated slashes are allowed. `.`
+    /// and `..` are resolved using the entries stored in each directory. Symbolic links are
+    /// followed, except in the last component unless the path ends in a slash; use
+    /// [`Ext2::lookup_follow`] to follow them there too.
+    ///
+    /// # Errors
+    /// - [`Error::NotFound`] if a component does not exist.
+    /// - [`Error::NotADirectory`] if a component other than the last is not a directory, or the
+    ///   path ends in a slash and does not name a directory.
+    /// - [`Error::NameTooLong`] if a component is longer than 255 bytes.
+    /// - [`Error::TooManySymlinks`] if more than [`MAX_SYMLINKS`] symbolic links are followed.
+    pub fn lookup(&self, path: impl AsRef<[u8]>) -> Result<InodeNumber> {
+        self.resolve(path.as_ref(), false)
+    }
+
+    /// Find the inode at `path`, like [`Ext2::lookup`], but also following a symbolic link in the
+    /// last component.
+    ///
+    /// # Errors
+    /// As for [`Ext2::lookup`].
+    pub fn lookup_follow(&self, path: impl AsRef<[u8]>) -> Result<InodeNumber> {
+        self.resolve(path.as_ref(), true)
+    }
+
+    fn resolve(&self, path: &[u8], follow_last: bool) -> Result<InodeNumber> {
+        let trailing_slash = path.ends_with(b"/");
+        let mut current = EXT2_ROOT_INO;
+        let mut links = 0;
+
+        // The components left to resolve, with the next one last.
+        let mut pending = Vec::new();
+        push_components(&mut pending, path);
+
+        while let Some(name) = pending.pop() {
+            let next = self.lookup_in(current, name.as_ref())?;
+            let is_last = pending.is_empty();
+            let inode = self.inode(next)?;
+
+            if inode.is_symlink() && (!is_last || follow_last || trailing_slash) {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(Error::TooManySymlinks);
+                }
+                let target = self.read_link_inode(next, &inode)?;
+                if target.is_empty() {
+                    return Err(Error::NotFound);
+                }
+                if target.starts_with(b"/") {
+                    current = EXT2_ROOT_INO;
+                }
+                push_components(&mut pending, &target);
+                continue;
+            }
+            current = next;
+        }
+
+        if trailing_slash && !self.inode(current)?.is_dir() {
+            return Err(Error::NotADirectory(current));
+        }
+        Ok(current)
+    }
+
+    /// Find the entry named `name` in the directory at inode `dir`.
+    fn lookup_in(&self, dir: InodeNumber, name: &[u8]) -> Result<InodeNumber> {
+        if name.len() > EXT2_NAME_LEN {
+            return Err(Error::NameTooLong);
+        }
+        self.find_entry(dir, name)?.ok_or(Error::NotFound)
+    }
+}
+
+/// Push the non-empty components of `path` onto `pending`, so that the first is popped first.
+fn push_components(pending: &mut Vec<Vec<u8>>, path: &[u8]) {
+    let components = path.split(|&b| b == b'/').filter(|name| !name.is_empty());
+    pending.extend(components.rev().map(<[u8]>::to_vec));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+    use crate::symlink::tests::{make_fast_symlink, make_slow_symlink};
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn resolves_paths() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.lookup("/").unwrap(), EXT2_ROOT_INO);
+        assert_eq!(fs.lookup("/hello.txt").unwrap(), 14);
+        assert_eq!(
+            fs.lookup("/test_directory/file_in_folder.txt").unwrap(),
+            1284
+        );
+        assert_eq!(fs.lookup("test_directory//").unwrap(), 1281);
+    }
+
+    #[test]
+    fn resolves_dot_and_dot_dot() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.lookup("/./test_directory/..").unwrap(), EXT2_ROOT_INO);
+        assert_eq!(fs.lookup("/..").unwrap(), EXT2_ROOT_INO);
+        assert_eq!(
+            fs.lookup("/test_directory/../test_directory/.").unwrap(),
+            1281
+        );
+    }
+
+    #[test]
+    fn reports_lookup_failures() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+        let long = [b'a'; 256];
+
+        assert_eq!(fs.lookup("/missing").unwrap_err(), Error::NotFound);
+        assert_eq!(
+            fs.lookup("/hello.txt/x").unwrap_err(),
+            Error::NotADirectory(14)
+        );
+        assert_eq!(
+            fs.lookup("/hello.txt/").unwrap_err(),
+            Error::NotADirectory(14)
+        );
+        assert_eq!(fs.lookup(long).unwrap_err(), Error::NameTooLong);
+    }
+
+    #[test]
+    fn follows_symlinks() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        make_fast_symlink(&mut fs, b"test_directory");
+
+        assert_eq!(fs.lookup("/hello.txt").unwrap(), 14);
+        assert_eq!(fs.lookup_follow("/hello.txt").unwrap(), 1281);
+        assert_eq!(fs.lookup("/hello.txt/").unwrap(), 1281);
+        assert_eq!(fs.lookup("/hello.txt/file_in_folder.txt").unwrap(), 1284);
+    }
+
+    #[test]
+    fn follows_absolute_and_relative_targets() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        make_slow_symlink(
+            &mut fs,
+            b"/test_directory/../test_directory/file_in_folder.txt",
+        );
+
+        assert_eq!(
+            fs.lookup_follow("/test_directory/../hello.txt").unwrap(),
+            1284
+        );
+    }
+
+    #[test]
+    fn detects_symlink_loops() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        make_fast_symlink(&mut fs, b"./hello.txt");
+
+        assert_eq!(fs.lookup("/hello.txt").unwrap(), 14);
+        assert_eq!(
+            fs.lookup_follow("/hello.txt").unwrap_err(),
+            Error::TooManySymlinks
+        );
+    }
+}
@@ -1,99 +1,157 @@
 //! The ext2 Schema.
 //!
 //! From `dylanmc/cs393_ext2` on github.
+//!
+//! ext2 is little-endian on disk, so multi-byte fields use the little-endian types below, which
+//! have no alignment requirement; read and write them with `get` and `set`.
 
 use bitflags::bitflags;
-use null_terminated::NulStr;
+use zerocopy::byteorder::{LittleEndian, I16, I32, U16, U32, U64};
+use zerocopy::{AsBytes, FromBytes, Unaligned};
+
+/// A little-endian `u16`, as stored on disk.
+pub type Le16 = U16<LittleEndian>;
+
+/// A little-endian `u32`, as stored on disk.
+pub type Le32 = U32<LittleEndian>;
+
+/// A little-endian `u64`, as stored on disk.
+pub type Le64 = U64<LittleEndian>;
+
+/// A little-endian `i16`, as stored on disk.
+pub type LeI16 = I16<LittleEndian>;
+
+/// A little-endian `i32`, as stored on disk.
+pub type LeI32 = I32<LittleEndian>;
+
 /// The ext2 magic number.
 pub const EXT2_MAGIC: u16 = 0xef53;
 
+/// The original ext2 revision, with fixed 128-byte inodes and no feature flags.
+pub const EXT2_GOOD_OLD_REV: u32 = 0;
+
+/// The "dynamic" revision, with variable inode sizes and feature flags.
+pub const EXT2_DYNAMIC_REV: u32 = 1;
+
+/// The largest supported `log_block_size`, giving 64 KiB blocks.
+pub const EXT2_MAX_LOG_BLOCK_SIZE: u32 = 6;
+
+/// The size of an inode in revision 0 filesystems.
+pub const EXT2_GOOD_OLD_INODE_SIZE: u16 = 128;
+
+/// The number of an inode, counting from 1.
+pub type InodeNumber = u32;
+
+/// The first inode which is not reserved in revision 0 filesystems.
+pub const EXT2_GOOD_OLD_FIRST_INO: InodeNumber = 11;
+
+/// The inode number of the root directory.
+pub const EXT2_ROOT_INO: InodeNumber = 2;
+
+/// The longest name a directory entry can hold.
+pub const EXT2_NAME_LEN: usize = 255;
+
+/// The most hard links an inode can have.
+pub const EXT2_LINK_MAX: u16 = 32000;
+
+/// The magic number starting an extended attribute block, and the attributes in an inode.
+pub const EXT2_XATTR_MAGIC: u32 = 0xEA02_0000;
+
+/// The superblock flag set when names are hashed as signed bytes.
+pub const EXT2_FLAGS_SIGNED_HASH: u32 = 0x0001;
+
+/// The superblock flag set when names are hashed as unsigned bytes.
+pub const EXT2_FLAGS_UNSIGNED_HASH: u32 = 0x0002;
+
 /// The ext2 superblock start address.
 pub const EXT2_START_OF_SUPERBLOCK: usize = 1024;
 
 /// The ext2 superblock end address.
 pub const EXT2_END_OF_SUPERBLOCK: usize = 2048;
 
+/// The superblock, describing the layout of the whole filesystem.
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 // https://wiki.osdev.org/Ext2
 pub struct Superblock {
     // taken from https://wiki.osdev.org/Ext2
     /// Total number of inodes in file system
-    pub inodes_count: u32,
+    pub inodes_count: Le32,
     /// Total number of blocks in file system
-    pub blocks_count: u32,
+    pub blocks_count: Le32,
     /// Number of blocks reserved for superuser (see offset 80)
-    pub r_blocks_count: u32,
+    pub r_blocks_count: Le32,
     /// Total number of unallocated blocks
-    pub free_blocks_count: u32,
+    pub free_blocks_count: Le32,
     /// Total number of unallocated inodes
-    pub free_inodes_count: u32,
+    pub free_inodes_count: Le32,
     /// Block number of the block containing the superblock
-    pub first_data_block: u32,
+    pub first_data_block: Le32,
     /// log2 (block size) - 10. (In other words, the number to shift 1,024
     /// to the left by to obtain the block size)
-    pub log_block_size: u32,
+    pub log_block_size: Le32,
     /// log2 (fragment size) - 10. (In other words, the number to shift
     /// 1,024 to the left by to obtain the fragment size)
-    pub log_frag_size: i32,
+    pub log_frag_size: LeI32,
     /// Number of blocks in each block group
-    pub blocks_per_group: u32,
+    pub blocks_per_group: Le32,
     /// Number of fragments in each block group
-    pub frags_per_group: u32,
+    pub frags_per_group: Le32,
     /// Number of inodes in each block group
-    pub inodes_per_group: u32,
+    pub inodes_per_group: Le32,
     /// Last mount time (in POSIX time)
-    pub mtime: u32,
+    pub mtime: Le32,
     /// Last written time (in POSIX time)
-    pub wtime: u32,
+    pub wtime: Le32,
     /// Number of times the volume has been mounted since its last
     /// consistency check (fsck)
-    pub mnt_count: u16,
+    pub mnt_count: Le16,
     /// Number of mounts allowed before a consistency check (fsck) must be
     /// done
-    pub max_mnt_count: i16,
+    pub max_mnt_count: LeI16,
     /// Ext2 signature (0xef53), used to help confirm the presence of Ext2
     /// on a volume
-    pub magic: u16,
+    pub magic: Le16,
     /// File system state (see `FS_CLEAN` and `FS_ERR`)
-    pub state: u16,
+    pub state: Le16,
     /// What to do when an error is detected (see `ERR_IGNORE`, `ERR_RONLY` and
     /// `ERR_PANIC`)
-    pub errors: u16,
+    pub errors: Le16,
     /// Minor portion of version (combine with Major portion below to
     /// construct full version field)
-    pub rev_minor: u16,
+    pub rev_minor: Le16,
     /// POSIX time of last consistency check (fsck)
-    pub lastcheck: u32,
+    pub lastcheck: Le32,
     /// Interval (in POSIX time) between forced consistency checks (fsck)
-    pub checkinterval: u32,
+    pub checkinterval: Le32,
     /// Operating system ID from which the filesystem on this volume was
     /// created
-    pub creator_os: u32,
+    pub creator_os: Le32,
     /// Major portion of version (combine with Minor portion above to
     /// construct full version field)
-    pub rev_major: u32,
+    pub rev_major: Le32,
     /// User ID that can use reserved blocks
-    pub block_uid: u16,
+    pub block_uid: Le16,
     /// Group ID that can use reserved blocks
-    pub block_gid: u16,
+    pub block_gid: Le16,
 
     /// First non-reserved inode in file system.
-    pub first_inode: u32,
+    pub first_inode: Le32,
     /// Size of each inode structure in bytes. - only 128 bytes seem used
     /// but modern EXT filesystems seem to use 256 bytes for each inode
-    pub inode_size: u16,
+    pub inode_size: Le16,
     /// Block group that this superblock is part of (if backup copy)
-    pub block_group: u16,
+    pub block_group: Le16,
     /// Optional features present (features that are not required to read
-    /// or write, but usually result in a performance increase)
-    pub features_opt: u32,
+    /// or write, but usually result in a performance increase; see
+    /// [`Superblock::compat_features`])
+    pub features_opt: Le32,
     /// Required features present (features that are required to be
-    /// supported to read or write)
-    pub features_req: u32,
+    /// supported to read or write; see [`Superblock::incompat_features`])
+    pub features_req: Le32,
     /// Features that if not supported, the volume must be mounted
-    /// read-only)
-    pub features_ronly: u32,
+    /// read-only (see [`Superblock::ro_compat_features`])
+    pub features_ronly: Le32,
     /// File system ID (what is output by blkid)
     pub fs_id: [u8; 16],
     /// Volume name (C-style string: characters terminated by a 0 byte)
@@ -102,127 +160,387 @@ pub struct Superblock {
     /// terminated by a 0 byte)
     pub last_mnt_path: [u8; 64],
     /// Compression algorithms used (see Required features above)
-    pub compression: u32,
+    pub compression: Le32,
     /// Number of blocks to preallocate for files
     pub prealloc_blocks_files: u8,
     /// Number of blocks to preallocate for directories
     pub prealloc_blocks_dirs: u8,
-    #[doc(hidden)]
-    _unused: [u8; 2],
+    /// Number of blocks reserved for growing the block group descriptor
+    /// table (with the `resize_inode` feature)
+    pub reserved_gdt_blocks: Le16,
     /// Journal ID (same style as the File system ID above)
     pub journal_id: [u8; 16],
     /// Journal inode
-    pub journal_inode: u32,
+    pub journal_inode: Le32,
     /// Journal device
-    pub journal_dev: u32,
+    pub journal_dev: Le32,
     /// Head of orphan inode list
-    pub journal_orphan_head: u32,
+    pub journal_orphan_head: Le32,
+
+    /// Seed for the hash of names in indexed directories
+    pub hash_seed: [Le32; 4],
+    /// Hash algorithm used by indexed directories by default
+    pub def_hash_version: u8,
+    /// Whether `jnl_blocks` holds a backup of the journal inode's block
+    /// pointers
+    pub jnl_backup_type: u8,
+    /// Size of each block group descriptor (with the `64bit` feature)
+    pub desc_size: Le16,
+    /// Default mount options
+    pub default_mount_opts: Le32,
+    /// First meta block group (with the `meta_bg` feature)
+    pub first_meta_bg: Le32,
+    /// When the filesystem was created (in POSIX time)
+    pub mkfs_time: Le32,
+    /// Backup of the journal inode's block pointers and size
+    pub jnl_blocks: [Le32; 17],
+
+    /// High 32 bits of `blocks_count` (with the `64bit` feature)
+    pub blocks_count_hi: Le32,
+    /// High 32 bits of `r_blocks_count` (with the `64bit` feature)
+    pub r_blocks_count_hi: Le32,
+    /// High 32 bits of `free_blocks_count` (with the `64bit` feature)
+    pub free_blocks_count_hi: Le32,
+    /// Size of the extra inode fields every inode has
+    pub min_extra_isize: Le16,
+    /// Size of the extra inode fields new inodes should have
+    pub want_extra_isize: Le16,
+    /// Miscellaneous flags, such as the default signedness of name hashes
+    pub flags: Le32,
+    /// Blocks to read or write on each disk before moving to the next
+    /// (for RAID)
+    pub raid_stride: Le16,
+    /// Seconds to wait between multiple mount protection checks
+    pub mmp_interval: Le16,
+    /// Block holding the multiple mount protection data
+    pub mmp_block: Le64,
+    /// Blocks on all data disks (for RAID)
+    pub raid_stripe_width: Le32,
+    /// log2 (number of block groups in a flexible block group)
+    pub log_groups_per_flex: u8,
+    /// Metadata checksum algorithm (1 is crc32c)
+    pub checksum_type: u8,
+    /// Version of the encryption in use
+    pub encryption_level: u8,
+    #[doc(hidden)]
+    _reserved_pad: u8,
+    /// KiB written over the lifetime of the filesystem
+    pub kbytes_written: Le64,
+    /// Inode of the active snapshot
+    pub snapshot_inum: Le32,
+    /// Sequential ID of the active snapshot
+    pub snapshot_id: Le32,
+    /// Blocks reserved for the active snapshot
+    pub snapshot_r_blocks_count: Le64,
+    /// Inode of the head of the snapshot list
+    pub snapshot_list: Le32,
+    /// Number of errors seen
+    pub error_count: Le32,
+    /// When the first error happened (in POSIX time)
+    pub first_error_time: Le32,
+    /// Inode involved in the first error
+    pub first_error_ino: Le32,
+    /// Block involved in the first error
+    pub first_error_block: Le64,
+    /// Function where the first error happened (C-style string)
+    pub first_error_func: [u8; 32],
+    /// Line number where the first error happened
+    pub first_error_line: Le32,
+    /// When the most recent error happened (in POSIX time)
+    pub last_error_time: Le32,
+    /// Inode involved in the most recent error
+    pub last_error_ino: Le32,
+    /// Line number where the most recent error happened
+    pub last_error_line: Le32,
+    /// Block involved in the most recent error
+    pub last_error_block: Le64,
+    /// Function where the most recent error happened (C-style string)
+    pub last_error_func: [u8; 32],
+    /// Mount options (C-style string)
+    pub mount_opts: [u8; 64],
+    /// Inode of the user quota file
+    pub usr_quota_inum: Le32,
+    /// Inode of the group quota file
+    pub grp_quota_inum: Le32,
+    /// Clusters of metadata overhead
+    pub overhead_clusters: Le32,
+    /// Block groups holding superblock backups (with the `sparse_super2`
+    /// feature)
+    pub backup_bgs: [Le32; 2],
+    /// Encryption algorithms in use
+    pub encrypt_algos: [u8; 4],
+    /// Salt for deriving encryption keys from passwords
+    pub encrypt_pw_salt: [u8; 16],
+    /// Inode of `lost+found`
+    pub lpf_ino: Le32,
+    /// Inode of the project quota file
+    pub prj_quota_inum: Le32,
+    /// Seed for metadata checksums (with the `csum_seed` feature)
+    pub checksum_seed: Le32,
+    /// High 8 bits of `wtime`
+    pub wtime_hi: u8,
+    /// High 8 bits of `mtime`
+    pub mtime_hi: u8,
+    /// High 8 bits of `mkfs_time`
+    pub mkfs_time_hi: u8,
+    /// High 8 bits of `lastcheck`
+    pub lastcheck_hi: u8,
+    /// High 8 bits of `first_error_time`
+    pub first_error_time_hi: u8,
+    /// High 8 bits of `last_error_time`
+    pub last_error_time_hi: u8,
+    /// Error code of the first error
+    pub first_error_errcode: u8,
+    /// Error code of the most recent error
+    pub last_error_errcode: u8,
+    /// Character encoding of names (with the `casefold` feature)
+    pub encoding: Le16,
+    /// Flags for the character encoding
+    pub encoding_flags: Le16,
+    /// Inode of the orphan file
+    pub orphan_file_inum: Le32,
+    #[doc(hidden)]
+    _reserved: [Le32; 94],
+    /// Checksum of the superblock (with the `metadata_csum` feature)
+    pub checksum: Le32,
 }
 
+const _: () = assert!(
+    core::mem::size_of::<Superblock>() == EXT2_END_OF_SUPERBLOCK - EXT2_START_OF_SUPERBLOCK
+);
+
+/// An entry in the block group descriptor table.
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 pub struct BlockGroupDescriptor {
     /// Block address of block usage bitmap
-    pub block_usage_addr: u32,
+    pub block_usage_addr: Le32,
     /// Block address of inode usage bitmap
-    pub inode_usage_addr: u32,
+    pub inode_usage_addr: Le32,
     /// Starting block address of inode table
-    pub inode_table_block: u32,
+    pub inode_table_block: Le32,
     /// Number of unallocated blocks in group
-    pub free_blocks_count: u16,
+    pub free_blocks_count: Le16,
     /// Number of unallocated inodes in group
-    pub free_inodes_count: u16,
+    pub free_inodes_count: Le16,
     /// Number of directories in group
-    pub dirs_count: u16,
+    pub dirs_count: Le16,
 
     _reserved: [u8; 14],
 }
 
+/// An inode, describing a single file, directory, or other object.
 #[repr(C)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 pub struct Inode {
-    /// Type and Permissions (see below)
-    pub type_perm: TypePerm,
+    /// Type and Permissions (see [`Inode::mode`])
+    pub type_perm: Le16,
     /// User ID
-    pub uid: u16,
+    pub uid: Le16,
     /// Lower 32 bits of size in bytes
-    pub size_low: u32,
+    pub size_low: Le32,
     /// Last Access Time (in POSIX time)
-    pub atime: u32,
+    pub atime: Le32,
     /// Creation Time (in POSIX time)
-    pub ctime: u32,
+    pub ctime: Le32,
     /// Last Modification time (in POSIX time)
-    pub mtime: u32,
+    pub mtime: Le32,
     /// Deletion time (in POSIX time)
-    pub dtime: u32,
+    pub dtime: Le32,
     /// Group ID
-    pub gid: u16,
+    pub gid: Le16,
     /// Count of hard links (directory entries) to this inode. When this
     /// reaches 0, the data blocks are marked as unallocated.
-    pub hard_links: u16,
+    pub hard_links: Le16,
     /// Count of disk sectors (not Ext2 blocks) in use by this inode, not
     /// counting the actual inode structure nor directory entries linking
     /// to the inode.
-    pub sectors_count: u32,
-    /// Flags
-    pub flags: u32,
+    pub sectors_count: Le32,
+    /// Flags (see [`Inode::inode_flags`])
+    pub flags: Le32,
     /// Operating System Specific value #1
     pub _os_specific_1: [u8; 4],
     /// Direct block pointers
-    pub direct_pointer: [u32; 12],
+    pub direct_pointer: [Le32; 12],
     /// Singly Indirect Block Pointer (Points to a block that is a list of
     /// block pointers to data)
-    pub indirect_pointer: u32,
+    pub indirect_pointer: Le32,
     /// Doubly Indirect Block Pointer (Points to a block that is a list of
     /// block pointers to Singly Indirect Blocks)
-    pub doubly_indirect: u32,
+    pub doubly_indirect: Le32,
     /// Triply Indirect Block Pointer (Points to a block that is a list of
     /// block pointers to Doubly Indirect Blocks)
-    pub triply_indirect: u32,
+    pub triply_indirect: Le32,
     /// Generation number (Primarily used for NFS)
-    pub gen_number: u32,
+    pub gen_number: Le32,
     /// In Ext2 version 0, this field is reserved. In version >= 1,
     /// Extended attribute block (File ACL).
-    pub ext_attribute_block: u32,
+    pub ext_attribute_block: Le32,
     /// In Ext2 version 0, this field is reserved. In version >= 1, Upper
     /// 32 bits of file size (if feature bit set) if it's a file,
     /// Directory ACL if it's a directory
-    pub size_high: u32,
+    pub size_high: Le32,
     /// Block address of fragment
-    pub frag_block_addr: u32,
+    pub frag_block_addr: Le32,
     /// Operating System Specific Value #2
     pub _os_specific_2: [u8; 12],
-    _padding: [u8; 128], // TODO: handle inode sizes != 128 according to superblock
 }
 
+/// The fields following the first 128 bytes of an inode, in filesystems with larger inodes.
+///
+/// Only the first `extra_isize` bytes of this structure are stored; any space after them, up to
+/// the superblock's inode size, holds extended attributes.
+#[repr(C)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
+pub struct InodeExtra {
+    /// Size of the extra fields in use, including this one
+    pub extra_isize: Le16,
+    /// Upper 16 bits of the inode checksum
+    pub checksum_hi: Le16,
+    /// Extra change time bits (see [`InodeExtra::ctime_nsec`])
+    pub ctime_extra: Le32,
+    /// Extra modification time bits (see [`InodeExtra::mtime_nsec`])
+    pub mtime_extra: Le32,
+    /// Extra access time bits (see [`InodeExtra::atime_nsec`])
+    pub atime_extra: Le32,
+    /// File creation time (in POSIX time)
+    pub crtime: Le32,
+    /// Extra file creation time bits (see [`InodeExtra::crtime_nsec`])
+    pub crtime_extra: Le32,
+    /// Upper 32 bits of the version number
+    pub version_hi: Le32,
+    /// Project ID
+    pub projid: Le32,
+}
+
+/// The header of an entry in a directory.
+///
+/// The header is followed by `name_length` bytes of name, which are not NUL-terminated, and then
+/// by padding up to `entry_size`.
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 pub struct DirectoryEntry {
     /// Inode
-    pub inode: u32,
+    pub inode: Le32,
     /// Total size of this entry (Including all subfields)
     /// (offset to start of next entry)
-    pub entry_size: u16,
+    pub entry_size: Le16,
     /// Name Length least-significant 8 bits
     pub name_length: u8,
     /// Type indicator (only if the feature bit for "directory entries have file type byte" is set, else this is the most-significant 8 bits of the Name Length)
-    pub type_indicator: TypeIndicator,
+    ///
+    /// See [`TypeIndicator`].
+    pub type_indicator: u8,
+}
 
-    pub name: NulStr,
+/// The information at the start of a hash tree root, after the `.` and `..` entries in the first
+/// block of an indexed directory.
+#[repr(C)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
+pub struct DxRootInfo {
+    /// Always zero
+    pub reserved_zero: Le32,
+    /// Hash algorithm (see [`HashVersion`](crate::HashVersion))
+    pub hash_version: u8,
+    /// Length of this structure, always 8
+    pub info_length: u8,
+    /// Levels of index nodes below the root, at most 1
+    pub indirect_levels: u8,
+    /// Unused flags
+    pub unused_flags: u8,
+}
+
+/// The header of the array of [`DxEntry`] in a hash tree root or node.
+///
+/// It takes the place of the first entry's hash, since the first entry covers every hash below
+/// the second's.
+#[repr(C)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
+pub struct DxCountLimit {
+    /// Number of entries the block has room for
+    pub limit: Le16,
+    /// Number of entries in use, including the first
+    pub count: Le16,
 }
 
-#[derive(Debug)]
+/// An entry in a hash tree root or node, pointing to the block covering the hashes from `hash`
+/// up to the next entry's.
+#[repr(C)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
+pub struct DxEntry {
+    /// Lowest hash covered by the block
+    pub hash: Le32,
+    /// Logical block number within the directory
+    pub block: Le32,
+}
+
+/// The header of an extended attribute block, followed by [`XattrEntry`] records.
+#[repr(C)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
+pub struct XattrHeader {
+    /// Magic number, [`EXT2_XATTR_MAGIC`]
+    pub magic: Le32,
+    /// Number of inodes sharing the block
+    pub refcount: Le32,
+    /// Number of blocks used, always 1
+    pub blocks: Le32,
+    /// Hash of the entries' hashes, or 0 if any of them is 0
+    pub hash: Le32,
+    /// Checksum of the block (ext4 only)
+    pub checksum: Le32,
+    /// Reserved
+    pub reserved: [Le32; 3],
+}
+
+/// An extended attribute, followed by the rest of its name after the namespace prefix and
+/// padding to a multiple of 4 bytes.
+///
+/// The list of entries ends with 4 zero bytes. Values are stored apart from the entries, at the
+/// end of the block or of the space in the inode.
+#[repr(C)]
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
+pub struct XattrEntry {
+    /// Length of the name
+    pub name_len: u8,
+    /// Namespace of the name
+    pub name_index: u8,
+    /// Offset of the value from the start of the block, or from the first entry in an inode
+    pub value_offset: Le16,
+    /// Inode holding the value (ext4 only), or 0 if it is stored with the entries
+    pub value_inode: Le32,
+    /// Length of the value
+    pub value_size: Le32,
+    /// Hash of the name and value
+    pub hash: Le32,
+}
+
+/// The type of the inode a directory entry points to.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TypeIndicator {
-    Unknown,
+    /// Unknown type
+    Unknown = 0,
+    /// Regular file
     Regular,
+    /// Directory
     Directory,
+    /// Character device
     Character,
+    /// Block device
     Block,
+    /// FIFO
     Fifo,
+    /// Unix socket
     Socket,
+    /// Symbolic link
     Symlink,
 }
 
 bitflags! {
+    /// The type and permissions of an inode.
+    ///
+    /// The type constants share bits with each other, so they cannot be tested with `contains`;
+    /// use [`FileMode`](crate::FileMode) to decode an inode's type and permissions.
     pub struct TypePerm: u16 {
         /// FIFO
         const FIFO = 0x1000;
@@ -264,3 +582,49 @@ bitflags! {
         const SET_UID = 0x800;
     }
 }
+
+bitflags! {
+    /// The flags of an inode, stored in its `flags` field.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct InodeFlags: u32 {
+        /// Blocks are overwritten when the file is deleted (not implemented by Linux)
+        const SECRM = 0x0000_0001;
+        /// Contents are kept when the file is deleted, so it can be undeleted (not implemented
+        /// by Linux)
+        const UNRM = 0x0000_0002;
+        /// The file is compressed
+        const COMPR = 0x0000_0004;
+        /// Changes are written synchronously
+        const SYNC = 0x0000_0008;
+        /// The file cannot be modified, renamed, linked to or deleted
+        const IMMUTABLE = 0x0000_0010;
+        /// The file can only be appended to, and cannot be renamed or deleted
+        const APPEND = 0x0000_0020;
+        /// The file is skipped by `dump`
+        const NODUMP = 0x0000_0040;
+        /// The access time is not updated
+        const NOATIME = 0x0000_0080;
+        /// Compressed data has been modified
+        const DIRTY = 0x0000_0100;
+        /// Some blocks are compressed
+        const COMPRBLK = 0x0000_0200;
+        /// The file is stored uncompressed
+        const NOCOMPR = 0x0000_0400;
+        /// Compression failed
+        const ECOMPR = 0x0000_0800;
+        /// The directory is indexed by a hash tree
+        const INDEX = 0x0000_1000;
+        /// AFS server directory
+        const IMAGIC = 0x0000_2000;
+        /// Data is written through the journal
+        const JOURNAL_DATA = 0x0000_4000;
+        /// The file's tail is not merged with others
+        const NOTAIL = 0x0000_8000;
+        /// Changes to the directory are written synchronously
+        const DIRSYNC = 0x0001_0000;
+        /// The directory is at the top of a hierarchy, spread out by the Orlov allocator
+        const TOPDIR = 0x0002_0000;
+        /// Reserved for the ext2 library
+        const RESERVED = 0x8000_0000;
+    }
+}
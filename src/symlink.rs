@@ -0,0 +1,129 @@
+//! Read symbolic links.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use zerocopy::AsBytes;
+
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::fs::Ext2;
+use crate::schema::{Inode, InodeNumber};
+
+/// The size of the block pointer area, which holds the target of a fast symlink.
+pub const FAST_SYMLINK_MAX: usize = 15 * size_of::<u32>();
+
+/// The offset of the block pointers in an inode.
+const BLOCK_POINTERS_OFFSET: usize = 40;
+
+impl Inode {
+    /// Whether this is a symbolic link whose target is stored in the block pointer area, given
+    /// the filesystem's block size.
+    ///
+    /// Fast symlinks have no data blocks, although they may have an extended attribute block.
+    #[must_use]
+    pub fn is_fast_symlink(&self, block_size: usize) -> bool {
+        let xattr_sectors = if self.ext_attribute_block.get() == 0 {
+            0
+        } else {
+            (block_size / 512) as u32
+        };
+        self.is_symlink() && self.sectors_count.get() == xattr_sectors
+    }
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Read the target of the symbolic link at inode number `number`.
+    ///
+    /// # Errors
+    /// If the inode cannot be read or is not a symbolic link, or the target cannot be read.
+    pub fn read_link(&self, number: InodeNumber) -> Result<Vec<u8>> {
+        let inode = self.inode(number)?;
+        if !inode.is_symlink() {
+            return Err(Error::NotASymlink(number));
+        }
+        self.read_link_inode(number, &inode)
+    }
+
+    /// Read the target of the symbolic link `inode`, which is inode number `number`.
+    pub(crate) fn read_link_inode(&self, number: InodeNumber, inode: &Inode) -> Result<Vec<u8>> {
+        let len = usize::try_from(inode.size()).map_err(|_| Error::CorruptInode(number))?;
+
+        if inode.is_fast_symlink(self.block_size()) {
+            if len > FAST_SYMLINK_MAX {
+                return Err(Error::CorruptInode(number));
+            }
+            let start = BLOCK_POINTERS_OFFSET;
+            return Ok(inode.as_bytes()[start..start + len].to_vec());
+        }
+
+        if len > self.block_size() {
+            return Err(Error::CorruptInode(number));
+        }
+        let mut target = vec![0; len];
+        self.read_data(inode, 0, &mut target)?;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    /// Turn `/hello.txt` into a fast symlink to `target`.
+    pub fn make_fast_symlink(fs: &mut Ext2<&mut [u8]>, target: &[u8]) {
+        let mut inode = fs.inode(14).unwrap();
+        inode.type_perm.set(0o120_777);
+        inode.size_low.set(target.len() as u32);
+        inode.sectors_count.set(0);
+        let area = &mut inode.as_bytes_mut()[BLOCK_POINTERS_OFFSET..][..FAST_SYMLINK_MAX];
+        area.fill(0);
+        area[..target.len()].copy_from_slice(target);
+        fs.write_inode(14, &inode).unwrap();
+    }
+
+    /// Turn `/hello.txt` into a slow symlink to `target`, stored in its data block.
+    pub fn make_slow_symlink(fs: &mut Ext2<&mut [u8]>, target: &[u8]) {
+        let mut inode = fs.inode(14).unwrap();
+        inode.type_perm.set(0o120_777);
+        inode.size_low.set(target.len() as u32);
+        fs.write_block_at(inode.direct_pointer[0].get(), 0, target)
+            .unwrap();
+        fs.write_inode(14, &inode).unwrap();
+    }
+
+    #[test]
+    fn reads_fast_symlink() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        make_fast_symlink(&mut fs, b"test_directory/file_in_folder.txt");
+
+        assert!(fs.inode(14).unwrap().is_fast_symlink(fs.block_size()));
+        assert_eq!(
+            fs.read_link(14).unwrap(),
+            b"test_directory/file_in_folder.txt"
+        );
+    }
+
+    #[test]
+    fn reads_slow_symlink() {
+        let target = [b'x'; 100];
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        make_slow_symlink(&mut fs, &target);
+
+        assert!(!fs.inode(14).unwrap().is_fast_symlink(fs.block_size()));
+        assert_eq!(fs.read_link(14).unwrap(), target);
+    }
+
+    #[test]
+    fn rejects_non_symlinks() {
+        let fs = Filesystem::new(IMAGE).unwrap();
+
+        assert_eq!(fs.read_link(14).unwrap_err(), Error::NotASymlink(14));
+    }
+}
@@ -0,0 +1,508 @@
+//! Create, write and truncate regular files.
+
+use alloc::vec;
+
+use crate::device::BlockDevice;
+use crate::dir::check_name;
+use crate::error::{Error, Result};
+use crate::features::{MountMode, RoCompatFeatures};
+use crate::fs::Ext2;
+use crate::mode::{FileMode, FileType, Permissions};
+use crate::schema::{Inode, InodeFlags, InodeNumber};
+
+/// The largest size a file can have without the large file feature.
+const MAX_SMALL_FILE: u64 = (1 << 31) - 1;
+
+/// A regular file open for reading and writing, created by [`Ext2::create`] or
+/// [`Ext2::open_mut`].
+///
+/// Every change is written to the device before the method making it returns.
+#[derive(Debug)]
+pub struct FileMut<'a, D> {
+    fs: &'a mut Ext2<D>,
+    number: InodeNumber,
+    inode: Inode,
+    pos: u64,
+    /// Whether a read has updated the access time yet.
+    accessed: bool,
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// Open inode number `number`, which must be a regular file, for reading and writing.
+    ///
+    /// # Errors
+    /// If the inode cannot be read or is not a regular file.
+    pub fn open_mut(&mut self, number: InodeNumber) -> Result<FileMut<'_, D>> {
+        let inode = self.inode(number)?;
+        if !inode.is_file() {
+            return Err(Error::NotAFile(number));
+        }
+        Ok(FileMut {
+            fs: self,
+            number,
+            inode,
+            pos: 0,
+            accessed: false,
+        })
+    }
+
+    /// Create an empty regular file named `name` with `permissions` in the directory at inode
+    /// number `parent`, and open it for writing.
+    ///
+    /// The file is owned by the filesystem's [`Credentials`](crate::Credentials).
+    ///
+    /// # Errors
+    /// - [`Error::InvalidName`] or [`Error::NameTooLong`] if `name` cannot be stored.
+    /// - [`Error::AlreadyExists`] if `parent` already has an entry named `name`.
+    /// - [`Error::NotADirectory`] if `parent` is not a directory.
+    /// - [`Error::NotPermitted`] if `parent` is immutable.
+    /// - [`Error::NoSpace`] if no inode, or no block for the directory entry, is available.
+    /// - If the filesystem cannot be read or written.
+    pub fn create(
+        &mut self,
+        parent: InodeNumber,
+        name: impl AsRef<[u8]>,
+        permissions: Permissions,
+    ) -> Result<FileMut<'_, D>> {
+        let name = name.as_ref();
+        check_name(name)?;
+
+        let number = self.allocate_inode(parent, false)?;
+        let inode = self.new_inode(FileMode::new(FileType::Regular, permissions));
+        let added = self
+            .init_inode(number, &inode)
+            .and_then(|()| self.add_entry(parent, name, number, FileType::Regular));
+        if let Err(err) = added {
+            // The entry was not added, so the inode is unreachable whether or not freeing it
+            // works, and the error which stopped the creation is the one to report. The type is
+            // passed in because the inode may not have been written.
+            let _ = self.free_inode(number, false);
+            return Err(err);
+        }
+        self.open_mut(number)
+    }
+}
+
+impl<D: BlockDevice> FileMut<'_, D> {
+    /// The number of the file's inode.
+    #[must_use]
+    pub const fn number(&self) -> InodeNumber {
+        self.number
+    }
+
+    /// The file's inode, including the changes made through this handle.
+    #[must_use]
+    pub const fn inode(&self) -> &Inode {
+        &self.inode
+    }
+
+    /// The length of the file, in bytes.
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.inode.size()
+    }
+
+    /// Whether the file is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The offset the next sequential read, write or seek is relative to.
+    #[must_use]
+    pub const fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Set the offset the next sequential read, write or seek is relative to.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Read bytes starting at `offset` into `buf`, returning how many were read.
+    ///
+    /// As for [`File::read_at`](crate::File::read_at), except that the first read through the
+    /// handle updates the access time, unless the file has [`InodeFlags::NOATIME`] or the
+    /// filesystem is read-only.
+    ///
+    /// # Errors
+    /// If the file's blocks cannot be mapped or read, or the access time cannot be written.
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let read = self.fs.read_data(&self.inode, offset, buf)?;
+        if !self.accessed
+            && !self.inode.inode_flags().contains(InodeFlags::NOATIME)
+            && self.fs.mount_mode() == MountMode::ReadWrite
+        {
+            let mut inode = self.inode.clone();
+            inode.atime.set(self.fs.now());
+            self.fs.write_inode(self.number, &inode)?;
+            self.inode = inode;
+        }
+        self.accessed = true;
+        Ok(read)
+    }
+
+    /// Write all of `buf` starting at `offset`, extending the file if needed.
+    ///
+    /// Blocks, and the indirect blocks leading to them, are allocated for holes and past the end
+    /// of the file. Writing past the end leaves a hole between the old end and `offset`. As in
+    /// Linux, the modification and change times are only updated if at least one byte is
+    /// written.
+    ///
+    /// # Errors
+    /// - [`Error::NoSpace`] if a block cannot be allocated. The bytes written before running out
+    ///   of space are kept, and the size covers them.
+    /// - [`Error::FileTooLarge`] if the write would go past the largest offset the block tree can
+    ///   address.
+    /// - [`Error::NotPermitted`] if the file is immutable, or is append-only and `offset` is not
+    ///   its end.
+    /// - If the filesystem cannot be read or written.
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        self.inode.check_mutable(self.number)?;
+        if self.inode.inode_flags().contains(InodeFlags::APPEND) && offset != self.len() {
+            return Err(Error::NotPermitted(self.number));
+        }
+        let mut written = 0;
+        let result = self.write_blocks(offset, buf, &mut written);
+        // Whatever was allocated and written must be recorded in the inode, even on failure.
+        if written > 0 {
+            let now = self.fs.now();
+            self.inode.mtime.set(now);
+            self.inode.ctime.set(now);
+        }
+        self.save()?;
+        result
+    }
+
+    /// Set the length of the file to `len`, freeing the blocks past the new end or leaving a hole
+    /// up to it.
+    ///
+    /// # Errors
+    /// - [`Error::NotPermitted`] if the file is immutable or append-only.
+    /// - If the file's blocks cannot be freed, or the inode cannot be written.
+    pub fn truncate(&mut self, len: u64) -> Result<()> {
+        self.inode.check_removable(self.number)?;
+        let block_size = self.fs.block_size() as u64;
+        let first = u32::try_from(len.div_ceil(block_size)).map_err(|_| Error::FileTooLarge)?;
+        self.fs.free_blocks_from(&mut self.inode, first)?;
+
+        // Bytes past the end of a partial last block must read as zeros if the file grows again.
+        let within = (len % block_size) as usize;
+        if within != 0 && len < self.len() {
+            let logical = (len / block_size) as u32;
+            if let Some(block) = self.inode.block_map(self.fs, logical)? {
+                let zeros = vec![0; block_size as usize - within];
+                self.fs.write_block_at(block, within, &zeros)?;
+            }
+        }
+
+        self.inode.set_size(len);
+        let now = self.fs.now();
+        self.inode.mtime.set(now);
+        self.inode.ctime.set(now);
+        self.save()
+    }
+
+    /// Write `buf` at `offset`, counting the bytes written in `done` so that a caller can tell
+    /// whether any were, even if the write fails part way.
+    fn write_blocks(&mut self, offset: u64, buf: &[u8], done: &mut usize) -> Result<()> {
+        let block_size = self.fs.block_size();
+        let mut goal = None;
+
+        while *done < buf.len() {
+            let pos = offset + *done as u64;
+            let within = (pos % block_size as u64) as usize;
+            let chunk = (buf.len() - *done).min(block_size - within);
+            let data = &buf[*done..*done + chunk];
+
+            let logical =
+                u32::try_from(pos / block_size as u64).map_err(|_| Error::FileTooLarge)?;
+            let (block, fresh) =
+                self.fs
+                    .map_or_allocate(self.number, &mut self.inode, logical, goal)?;
+            if fresh && chunk < block_size {
+                // A new block may hold stale data, so write it whole.
+                let mut whole = vec![0; block_size];
+                whole[within..within + chunk].copy_from_slice(data);
+                self.fs.write_block_at(block, 0, &whole)?;
+            } else {
+                self.fs.write_block_at(block, within, data)?;
+            }
+
+            *done += chunk;
+            goal = Some(block + 1);
+            let end = offset + *done as u64;
+            if end > self.len() {
+                self.inode.set_size(end);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the inode back, marking the filesystem as holding large files if this one is.
+    fn save(&mut self) -> Result<()> {
+        if self.len() > MAX_SMALL_FILE
+            && !self
+                .fs
+                .superblock()
+                .ro_compat_features()
+                .contains(RoCompatFeatures::LARGE_FILE)
+        {
+            let mut superblock = self.fs.superblock().clone();
+            let features = superblock.ro_compat_features() | RoCompatFeatures::LARGE_FILE;
+            superblock.features_ronly.set(features.bits());
+            self.fs.write_superblock(superblock)?;
+        }
+        self.fs.write_inode(self.number, &self.inode)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D: BlockDevice> std::io::Read for FileMut<'_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.read_at(self.pos, buf)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D: BlockDevice> std::io::Write for FileMut<'_, D> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_at(self.pos, buf)?;
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D: BlockDevice> std::io::Seek for FileMut<'_, D> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.pos = crate::file::seek_position(pos, self.pos, self.len())?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::Filesystem;
+    use crate::schema::EXT2_ROOT_INO;
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    const RW_R_R: Permissions = Permissions::from_bits_retain(0o644);
+
+    #[test]
+    fn creates_writes_and_reads_back() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut file = fs.create(EXT2_ROOT_INO, "new.txt", RW_R_R).unwrap();
+        file.write_at(0, b"Hello, ").unwrap();
+        file.write_at(7, b"writer!").unwrap();
+        let number = file.number();
+
+        let fs = Filesystem::new(&image[..]).unwrap();
+        assert_eq!(fs.lookup("/new.txt").unwrap(), number);
+        let file = fs.open(number).unwrap();
+        let mut buf = [0; 32];
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 14);
+        assert_eq!(&buf[..14], b"Hello, writer!");
+        assert_eq!(file.inode().sectors_count.get(), 2);
+        assert_eq!(file.inode().mode().unwrap().to_raw(), 0o100_644);
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546 - 1);
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496 - 1);
+    }
+
+    #[test]
+    fn allocates_indirect_blocks() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut file = fs.create(EXT2_ROOT_INO, "sparse", RW_R_R).unwrap();
+        file.write_at(20 * 1024, b"far").unwrap();
+
+        assert_eq!(file.len(), 20 * 1024 + 3);
+        assert_ne!(file.inode().indirect_pointer.get(), 0);
+        // The indirect block and the data block.
+        assert_eq!(file.inode().sectors_count.get(), 4);
+        let mut buf = [0xff; 4];
+        assert_eq!(file.read_at(20 * 1024 - 1, &mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"\0far");
+    }
+
+    #[test]
+    fn truncating_frees_blocks() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut file = fs.create(EXT2_ROOT_INO, "big", RW_R_R).unwrap();
+        file.write_at(0, &vec![7; 300 * 1024]).unwrap();
+        file.truncate(0).unwrap();
+
+        assert!(file.is_empty());
+        assert_eq!(file.inode().sectors_count.get(), 0);
+        assert_eq!(file.inode().block_pointers(), [0; 15]);
+        assert_eq!(fs.superblock().free_blocks_count.get(), 9496);
+        assert_eq!(fs.groups()[0].free_blocks_count.get(), 7814);
+    }
+
+    #[test]
+    fn shrinking_zeroes_the_tail() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut file = fs.create(EXT2_ROOT_INO, "tail", RW_R_R).unwrap();
+        file.write_at(0, &[0xaa; 2000]).unwrap();
+        file.truncate(100).unwrap();
+        file.truncate(2000).unwrap();
+
+        let mut buf = [0xff; 2000];
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 2000);
+        assert!(buf[..100].iter().all(|&b| b == 0xaa));
+        assert!(buf[100..].iter().all(|&b| b == 0));
+        assert_eq!(file.inode().sectors_count.get(), 2);
+    }
+
+    #[test]
+    fn refuses_existing_and_invalid_names() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+
+        assert_eq!(
+            fs.create(EXT2_ROOT_INO, "hello.txt", RW_R_R).unwrap_err(),
+            Error::AlreadyExists
+        );
+        assert_eq!(
+            fs.create(EXT2_ROOT_INO, "a/b", RW_R_R).unwrap_err(),
+            Error::InvalidName
+        );
+        assert_eq!(
+            fs.create(14, "x", RW_R_R).unwrap_err(),
+            Error::NotADirectory(14)
+        );
+        assert_eq!(fs.superblock().free_inodes_count.get(), 2546);
+    }
+
+    #[test]
+    fn timestamps_changes_with_the_clock() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.set_clock(|| 1_700_000_000);
+        let mut file = fs.open_mut(14).unwrap();
+        file.write_at(19, b"more").unwrap();
+
+        assert_eq!(file.len(), 23);
+        assert_eq!(file.inode().mtime.get(), 1_700_000_000);
+        assert_eq!(file.inode().ctime.get(), 1_700_000_000);
+    }
+
+    #[test]
+    fn leaves_the_timestamps_of_empty_writes_alone() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let before = fs.inode(14).unwrap();
+        fs.set_clock(|| 1_700_000_000);
+        let mut file = fs.open_mut(14).unwrap();
+        file.write_at(19, b"").unwrap();
+        file.write_at(1 << 40, b"").unwrap();
+
+        let after = fs.inode(14).unwrap();
+        assert_eq!(after.size(), before.size());
+        assert_eq!(after.mtime.get(), before.mtime.get());
+        assert_eq!(after.ctime.get(), before.ctime.get());
+    }
+
+    #[test]
+    fn leaves_the_timestamps_alone_if_nothing_was_written() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let before = fs.inode(14).unwrap();
+        fs.set_clock(|| 1_700_000_000);
+        let mut file = fs.open_mut(14).unwrap();
+
+        assert_eq!(file.write_at(1 << 40, b"far"), Err(Error::FileTooLarge));
+        let after = fs.inode(14).unwrap();
+        assert_eq!(after.size(), before.size());
+        assert_eq!(after.mtime.get(), before.mtime.get());
+        assert_eq!(after.ctime.get(), before.ctime.get());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn implements_write_and_seek() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let mut file = fs.open_mut(14).unwrap();
+        file.seek(SeekFrom::End(-7)).unwrap();
+        file.write_all(b"there!\n").unwrap();
+        file.rewind().unwrap();
+
+        let mut contents = std::string::String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "Hello, ext2 there!\n");
+    }
+
+    #[test]
+    fn honours_immutable_and_append_only_flags() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let number = fs.create(EXT2_ROOT_INO, "log", RW_R_R).unwrap().number();
+        fs.set_inode_flags(number, InodeFlags::APPEND).unwrap();
+
+        let mut file = fs.open_mut(number).unwrap();
+        file.write_at(0, b"one,").unwrap();
+        file.write_at(4, b"two").unwrap();
+        assert_eq!(file.write_at(0, b"x"), Err(Error::NotPermitted(number)));
+        assert_eq!(file.truncate(0), Err(Error::NotPermitted(number)));
+        assert_eq!(file.len(), 7);
+
+        fs.set_inode_flags(number, InodeFlags::IMMUTABLE).unwrap();
+        let mut file = fs.open_mut(number).unwrap();
+        assert_eq!(file.write_at(7, b"x"), Err(Error::NotPermitted(number)));
+        assert_eq!(file.truncate(0), Err(Error::NotPermitted(number)));
+        let mut buf = [0; 8];
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 7);
+        assert_eq!(&buf[..7], b"one,two");
+    }
+
+    #[test]
+    fn updates_access_time_unless_noatime() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.set_clock(|| 1_000);
+        let mut buf = [0; 4];
+        fs.open_mut(14).unwrap().read_at(0, &mut buf).unwrap();
+        assert_eq!(fs.inode(14).unwrap().atime.get(), 1_000);
+
+        fs.set_clock(|| 2_000);
+        let mut file = fs.open_mut(14).unwrap();
+        file.read_at(0, &mut buf).unwrap();
+        file.fs.set_clock(|| 3_000);
+        file.read_at(0, &mut buf).unwrap();
+        assert_eq!(fs.inode(14).unwrap().atime.get(), 2_000);
+
+        fs.set_inode_flags(14, InodeFlags::NOATIME).unwrap();
+        fs.open_mut(14).unwrap().read_at(0, &mut buf).unwrap();
+        assert_eq!(fs.inode(14).unwrap().atime.get(), 2_000);
+    }
+
+    #[test]
+    fn reads_read_only_devices_without_updating_the_access_time() {
+        let mut fs = Filesystem::new(IMAGE).unwrap();
+        let atime = fs.inode(14).unwrap().atime.get();
+        fs.set_clock(|| 1_000);
+        let mut file = fs.open_mut(14).unwrap();
+        let mut buf = [0; 5];
+
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"Hello");
+        assert_eq!(file.inode().atime.get(), atime);
+    }
+}
@@ -0,0 +1,766 @@
+//! Read and write extended attributes, stored in the space after an inode's extra fields and in
+//! an attribute block which inodes with the same attributes may share.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::acl::{Access, Acl};
+use crate::device::BlockDevice;
+use crate::error::{Error, Result};
+use crate::features::CompatFeatures;
+use crate::fs::Ext2;
+use crate::mode::Permissions;
+use crate::schema::{Inode, InodeNumber, XattrEntry, XattrHeader, EXT2_XATTR_MAGIC};
+
+/// The namespaces attribute names may be in, by prefix, and the index stored in their entries.
+///
+/// The POSIX ACLs are whole names, with nothing after the prefix, and come before the rest of
+/// the `system.` namespace so that they are matched first.
+const NAMESPACES: [(&[u8], u8); 6] = [
+    (b"user.", 1),
+    (b"system.posix_acl_access", 2),
+    (b"system.posix_acl_default", 3),
+    (b"trusted.", 4),
+    (b"security.", 6),
+    (b"system.", 7),
+];
+
+/// The index of the `user.` namespace.
+const USER_INDEX: u8 = 1;
+
+/// The index of the POSIX access ACL.
+const ACL_ACCESS_INDEX: u8 = 2;
+
+/// The index of the POSIX default ACL.
+const ACL_DEFAULT_INDEX: u8 = 3;
+
+/// The indexes of the POSIX ACLs, whose values are checked before they are stored.
+const ACL_INDEXES: [u8; 2] = [ACL_ACCESS_INDEX, ACL_DEFAULT_INDEX];
+
+/// The index of the `trusted.` namespace.
+const TRUSTED_INDEX: u8 = 4;
+
+/// The size of the magic number before the attributes in an inode.
+const INODE_MAGIC_SIZE: usize = size_of::<u32>();
+
+/// An extended attribute, with its name split into the namespace index and the rest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Attr {
+    index: u8,
+    name: Vec<u8>,
+    value: Vec<u8>,
+}
+
+/// Where the attributes are stored in a block or in the space in an inode.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    /// The offset of the first entry.
+    entries: usize,
+    /// The offset value offsets are relative to.
+    values: usize,
+}
+
+/// The layout of an attribute block, whose entries follow the header.
+const BLOCK_LAYOUT: Layout = Layout {
+    entries: size_of::<XattrHeader>(),
+    values: 0,
+};
+
+/// The layout of the space in an inode, whose entries follow the magic number.
+const INODE_LAYOUT: Layout = Layout {
+    entries: INODE_MAGIC_SIZE,
+    values: INODE_MAGIC_SIZE,
+};
+
+/// Round `len` up to a multiple of 4 bytes, the alignment of entries and values.
+const fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// The length of the entry for a name of `name_len` bytes.
+const fn entry_len(name_len: usize) -> usize {
+    padded(size_of::<XattrEntry>() + name_len)
+}
+
+/// Split `name` into its namespace index and the rest.
+///
+/// # Errors
+/// - [`Error::UnsupportedNamespace`] if `name` is not in a supported namespace.
+/// - [`Error::NameTooLong`] if the rest of the name is longer than an entry can hold.
+fn split_name(name: &[u8]) -> Result<(u8, &[u8])> {
+    let (prefix, index) = NAMESPACES
+        .iter()
+        .find(|(prefix, _)| {
+            if prefix.ends_with(b".") {
+                name.len() > prefix.len() && name.starts_with(prefix)
+            } else {
+                name == *prefix
+            }
+        })
+        .ok_or(Error::UnsupportedNamespace)?;
+    let rest = &name[prefix.len()..];
+    if rest.len() > usize::from(u8::MAX) {
+        return Err(Error::NameTooLong);
+    }
+    Ok((*index, rest))
+}
+
+/// The full name of an attribute in namespace `index`, or `None` if the namespace is unknown.
+fn full_name(index: u8, name: &[u8]) -> Option<Vec<u8>> {
+    let (prefix, _) = NAMESPACES.iter().find(|(_, known)| *known == index)?;
+    Some([prefix, name].concat())
+}
+
+/// The hash of an entry for `name` with `value`.
+///
+/// Linux hashes names as `char`, which is signed on the platforms ext2 came from.
+fn entry_hash(name: &[u8], value: &[u8]) -> u32 {
+    let mut hash = 0u32;
+    for &byte in name {
+        hash = (hash << 5) ^ (hash >> 27) ^ (byte as i8 as u32);
+    }
+    for chunk in value.chunks(4) {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        hash = (hash << 16) ^ (hash >> 16) ^ u32::from_le_bytes(word);
+    }
+    hash
+}
+
+/// The hash of an attribute block, combining the hashes of its entries.
+fn block_hash(entry_hashes: impl IntoIterator<Item = u32>) -> u32 {
+    let mut hash = 0u32;
+    for entry in entry_hashes {
+        if entry == 0 {
+            return 0;
+        }
+        hash = (hash << 16) ^ (hash >> 16) ^ entry;
+    }
+    hash
+}
+
+/// Parse the attributes in `region`, laid out as `layout`, which belong to inode number
+/// `number`.
+fn parse_attrs(number: InodeNumber, region: &[u8], layout: Layout) -> Result<Vec<Attr>> {
+    let corrupt = Error::CorruptInode(number);
+    let mut attrs = Vec::new();
+    let mut offset = layout.entries;
+    loop {
+        let rest = region.get(offset..).ok_or(corrupt)?;
+        if rest.get(..4).ok_or(corrupt)? == [0; 4] {
+            return Ok(attrs);
+        }
+        let entry = XattrEntry::read_from_prefix(rest).ok_or(corrupt)?;
+        if entry.value_inode.get() != 0 {
+            return Err(corrupt);
+        }
+
+        let name_start = offset + size_of::<XattrEntry>();
+        let name = region
+            .get(name_start..name_start + usize::from(entry.name_len))
+            .ok_or(corrupt)?;
+        let value_size = usize::try_from(entry.value_size.get()).map_err(|_| corrupt)?;
+        let value = if value_size == 0 {
+            &[][..]
+        } else {
+            // The size is read from the disk, so it could overflow on 32-bit targets.
+            let start = layout.values + usize::from(entry.value_offset.get());
+            let end = start.checked_add(value_size).ok_or(corrupt)?;
+            region.get(start..end).ok_or(corrupt)?
+        };
+        attrs.push(Attr {
+            index: entry.name_index,
+            name: name.to_vec(),
+            value: value.to_vec(),
+        });
+        offset += entry_len(name.len());
+    }
+}
+
+/// Lay out `attrs` in a region of `len` bytes as `layout`, returning the entries' hashes too.
+///
+/// Returns `None` if they do not fit.
+fn build_attrs(attrs: &[Attr], len: usize, layout: Layout) -> Option<(Vec<u8>, Vec<u32>)> {
+    let entries: usize = attrs.iter().map(|attr| entry_len(attr.name.len())).sum();
+    let values: usize = attrs.iter().map(|attr| padded(attr.value.len())).sum();
+    if layout.entries + entries + size_of::<u32>() + values > len {
+        return None;
+    }
+
+    let mut region = vec![0; len];
+    let mut hashes = Vec::with_capacity(attrs.len());
+    let mut offset = layout.entries;
+    let mut value_start = len;
+    for attr in attrs {
+        let mut entry = XattrEntry::new_zeroed();
+        entry.name_len = attr.name.len() as u8;
+        entry.name_index = attr.index;
+        if !attr.value.is_empty() {
+            value_start -= padded(attr.value.len());
+            region[value_start..value_start + attr.value.len()].copy_from_slice(&attr.value);
+            entry.value_offset.set((value_start - layout.values) as u16);
+        }
+        entry.value_size.set(attr.value.len() as u32);
+        let hash = entry_hash(&attr.name, &attr.value);
+        entry.hash.set(hash);
+        hashes.push(hash);
+
+        let name_start = offset + size_of::<XattrEntry>();
+        region[offset..name_start].copy_from_slice(entry.as_bytes());
+        region[name_start..name_start + attr.name.len()].copy_from_slice(&attr.name);
+        offset += entry_len(attr.name.len());
+    }
+    Some((region, hashes))
+}
+
+impl<D: BlockDevice> Ext2<D> {
+    /// The full names of the extended attributes of inode number `number`, those in the inode
+    /// first.
+    ///
+    /// Attributes in namespaces this crate does not know are left out, as are those in the
+    /// `trusted.` namespace unless the filesystem's [`Credentials`](crate::Credentials) are the
+    /// superuser's.
+    ///
+    /// # Errors
+    /// If the inode or its attribute block cannot be read, or its attributes are malformed.
+    pub fn listxattr(&self, number: InodeNumber) -> Result<Vec<Vec<u8>>> {
+        let inode = self.inode(number)?;
+        let (in_inode, in_block) = self.read_xattrs(number, &inode)?;
+        Ok(in_inode
+            .iter()
+            .chain(&in_block)
+            .filter(|attr| self.may_see(attr.index))
+            .filter_map(|attr| full_name(attr.index, &attr.name))
+            .collect())
+    }
+
+    /// The value of the extended attribute named `name`, including its namespace prefix, of
+    /// inode number `number`.
+    ///
+    /// Reading an attribute in the `user.` namespace needs read access to the inode, as checked
+    /// by [`Ext2::check_access`] for the filesystem's [`Credentials`](crate::Credentials).
+    ///
+    /// # Errors
+    /// - [`Error::UnsupportedNamespace`] if `name` is not in the `user.`, `trusted.`,
+    ///   `security.` or `system.` namespaces.
+    /// - [`Error::NoAttribute`] if the inode has no such attribute, it is in the `trusted.`
+    ///   namespace and the filesystem's credentials are not the superuser's, or it is in the
+    ///   `user.` namespace and the inode is not a regular file or directory.
+    /// - [`Error::AccessDenied`] if it is in the `user.` namespace and the credentials may not
+    ///   read the inode.
+    /// - If the inode or its attribute block cannot be read, or its attributes are malformed.
+    pub fn getxattr(&self, number: InodeNumber, name: impl AsRef<[u8]>) -> Result<Vec<u8>> {
+        let (index, name) = split_name(name.as_ref())?;
+        let inode = self.inode(number)?;
+        self.check_xattr_readable(number, &inode, index)?;
+        let (in_inode, in_block) = self.read_xattrs(number, &inode)?;
+        in_inode
+            .into_iter()
+            .chain(in_block)
+            .find(|attr| attr.index == index && attr.name == name)
+            .map(|attr| attr.value)
+            .ok_or(Error::NoAttribute)
+    }
+
+    /// Set the extended attribute named `name`, including its namespace prefix, of inode number
+    /// `number` to `value`, adding it if the inode does not have it.
+    ///
+    /// The attribute is stored in the inode if it fits there, and otherwise in the inode's
+    /// attribute block, which is allocated if needed. A block shared with other inodes is copied
+    /// rather than changed. The inode's change time is updated.
+    ///
+    /// As in Linux, changing an attribute in the `user.` namespace needs write access to the
+    /// inode, as checked by [`Ext2::check_access`] for the filesystem's
+    /// [`Credentials`](crate::Credentials), and changing a POSIX ACL needs the credentials to
+    /// own the inode or be the superuser's.
+    ///
+    /// Setting the access ACL also sets the inode's permission bits from it, and the ACL is only
+    /// stored if it grants more than those bits can express.
+    ///
+    /// # Errors
+    /// - [`Error::UnsupportedNamespace`] if `name` is not in the `user.`, `trusted.`,
+    ///   `security.` or `system.` namespaces.
+    /// - [`Error::NameTooLong`] if the name after the prefix is longer than 255 bytes.
+    /// - [`Error::InvalidAcl`] if the attribute is a POSIX ACL, and `value` is not a valid
+    ///   [`Acl`].
+    /// - [`Error::NotPermitted`] if the inode is immutable or append-only, the attribute is in
+    ///   the `trusted.` namespace and the filesystem's credentials are not the superuser's, it
+    ///   is a POSIX ACL and the credentials are not the owner's or the superuser's, or it is in
+    ///   the `user.` namespace and the inode is not a regular file or directory, or is a sticky
+    ///   directory the credentials do not own.
+    /// - [`Error::AccessDenied`] if the attribute is in the `user.` namespace and the
+    ///   credentials may not write to the inode, or it is the default ACL and the inode is not
+    ///   a directory.
+    /// - [`Error::NoSpace`] if the inode's attributes do not fit in a block, or a block cannot
+    ///   be allocated.
+    /// - If the inode or its attribute block cannot be read or written, or its attributes are
+    ///   malformed.
+    pub fn setxattr(
+        &mut self,
+        number: InodeNumber,
+        name: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        let (index, name) = split_name(name.as_ref())?;
+        let value = value.as_ref();
+        let acl = if ACL_INDEXES.contains(&index) {
+            let acl = Acl::from_bytes(value).filter(Acl::is_valid);
+            Some(acl.ok_or(Error::InvalidAcl)?)
+        } else {
+            None
+        };
+        let mut inode = self.inode(number)?;
+        self.check_xattr_writable(number, &inode, index)?;
+        if index == ACL_DEFAULT_INDEX && !inode.is_dir() {
+            return Err(Error::AccessDenied(number));
+        }
+        let (mut in_inode, mut in_block) = self.read_xattrs(number, &inode)?;
+        in_inode.retain(|attr| attr.index != index || attr.name != name);
+        in_block.retain(|attr| attr.index != index || attr.name != name);
+
+        if let Some(acl) = acl.filter(|_| index == ACL_ACCESS_INDEX) {
+            if self.apply_access_acl(&mut inode, &acl) {
+                return self.store_xattrs(number, &mut inode, &in_inode, &in_block);
+            }
+        }
+        let attr = Attr {
+            index,
+            name: name.to_vec(),
+            value: value.to_vec(),
+        };
+        let space = self.inode_xattr_space(number)?.len();
+        let mut with_attr = in_inode.clone();
+        with_attr.push(attr.clone());
+        if build_attrs(&with_attr, space, INODE_LAYOUT).is_some() {
+            in_inode = with_attr;
+        } else {
+            in_block.push(attr);
+            // Linux keeps the entries of a block sorted, so that equal blocks can be shared.
+            in_block.sort_by(|a, b| {
+                (a.index, a.name.len(), &a.name).cmp(&(b.index, b.name.len(), &b.name))
+            });
+            if build_attrs(&in_block, self.block_size(), BLOCK_LAYOUT).is_none() {
+                return Err(Error::NoSpace);
+            }
+        }
+        self.store_xattrs(number, &mut inode, &in_inode, &in_block)
+    }
+
+    /// Remove the extended attribute named `name`, including its namespace prefix, from inode
+    /// number `number`.
+    ///
+    /// The inode's attribute block is released once it holds no attributes, and its change time
+    /// is updated.
+    ///
+    /// # Errors
+    /// - [`Error::UnsupportedNamespace`] if `name` is not in the `user.`, `trusted.`,
+    ///   `security.` or `system.` namespaces.
+    /// - [`Error::NoAttribute`] if the inode has no such attribute.
+    /// - [`Error::NotPermitted`] or [`Error::AccessDenied`] as for [`Ext2::setxattr`].
+    /// - If the inode or its attribute block cannot be read or written, or its attributes are
+    ///   malformed.
+    pub fn removexattr(&mut self, number: InodeNumber, name: impl AsRef<[u8]>) -> Result<()> {
+        let (index, name) = split_name(name.as_ref())?;
+        let mut inode = self.inode(number)?;
+        self.check_xattr_writable(number, &inode, index)?;
+        let (mut in_inode, mut in_block) = self.read_xattrs(number, &inode)?;
+        let count = in_inode.len() + in_block.len();
+        in_inode.retain(|attr| attr.index != index || attr.name != name);
+        in_block.retain(|attr| attr.index != index || attr.name != name);
+        if in_inode.len() + in_block.len() == count {
+            return Err(Error::NoAttribute);
+        }
+        self.store_xattrs(number, &mut inode, &in_inode, &in_block)
+    }
+
+    /// Drop the attribute block of `inode`, which is inode number `number`, freeing it unless
+    /// other inodes share it.
+    ///
+    /// The caller must write the inode back.
+    pub(crate) fn release_xattr_block(
+        &mut self,
+        number: InodeNumber,
+        inode: &mut Inode,
+    ) -> Result<()> {
+        let block = inode.ext_attribute_block.get();
+        if block == 0 {
+            return Ok(());
+        }
+        let mut header = self.read_xattr_header(number, block)?;
+        if header.refcount.get() > 1 {
+            header.refcount.set(header.refcount.get() - 1);
+            self.write_block_at(block, 0, header.as_bytes())?;
+        } else {
+            self.free_block(block)?;
+        }
+        inode.ext_attribute_block.set(0);
+        inode.sectors_count.set(
+            inode
+                .sectors_count
+                .get()
+                .saturating_sub(self.sectors_per_block()),
+        );
+        Ok(())
+    }
+
+    /// Whether the filesystem's credentials may see attributes in namespace `index`.
+    fn may_see(&self, index: u8) -> bool {
+        index != TRUSTED_INDEX || self.credentials().uid == 0
+    }
+
+    /// Fail unless the filesystem's credentials may read the attributes in namespace `index` of
+    /// `inode`, which is inode number `number`.
+    fn check_xattr_readable(&self, number: InodeNumber, inode: &Inode, index: u8) -> Result<()> {
+        let user_allowed = inode.is_file() || inode.is_dir();
+        if !self.may_see(index) || (index == USER_INDEX && !user_allowed) {
+            return Err(Error::NoAttribute);
+        }
+        if index == USER_INDEX {
+            let credentials = self.credentials();
+            self.check_access(number, credentials.uid, &[credentials.gid], Access::READ)?;
+        }
+        Ok(())
+    }
+
+    /// Fail unless the filesystem's credentials may change the attributes in namespace `index`
+    /// of `inode`, which is inode number `number`.
+    fn check_xattr_writable(&self, number: InodeNumber, inode: &Inode, index: u8) -> Result<()> {
+        inode.check_removable(number)?;
+        let credentials = self.credentials();
+        let owner = credentials.uid == 0 || credentials.uid == inode.owner_uid();
+        let user_allowed = inode.is_file() || inode.is_dir();
+        // Only the owner may label the entries of a sticky directory, as they may only remove
+        // their own.
+        let sticky = inode.is_dir()
+            && inode
+                .mode()
+                .is_some_and(|mode| mode.permissions.contains(Permissions::STICKY));
+        let denied = match index {
+            USER_INDEX => !user_allowed || (sticky && !owner),
+            TRUSTED_INDEX => credentials.uid != 0,
+            _ => ACL_INDEXES.contains(&index) && !owner,
+        };
+        if denied {
+            return Err(Error::NotPermitted(number));
+        }
+        if index == USER_INDEX {
+            self.check_access(number, credentials.uid, &[credentials.gid], Access::WRITE)?;
+        }
+        Ok(())
+    }
+
+    /// The attributes of `inode`, which is inode number `number`: those in the inode, and those
+    /// in its attribute block.
+    fn read_xattrs(&self, number: InodeNumber, inode: &Inode) -> Result<(Vec<Attr>, Vec<Attr>)> {
+        let space = self.inode_xattr_space(number)?;
+        let in_inode = match space.get(..INODE_MAGIC_SIZE) {
+            Some(magic) if magic == EXT2_XATTR_MAGIC.to_le_bytes() => {
+                parse_attrs(number, &space, INODE_LAYOUT)?
+            }
+            _ => Vec::new(),
+        };
+
+        let block = inode.ext_attribute_block.get();
+        let in_block = if block == 0 {
+            Vec::new()
+        } else {
+            self.read_xattr_header(number, block)?;
+            let mut data = vec![0; self.block_size()];
+            self.read_block_at(block, 0, &mut data)?;
+            parse_attrs(number, &data, BLOCK_LAYOUT)?
+        };
+        Ok((in_inode, in_block))
+    }
+
+    /// Read and check the header of attribute block `block`, which belongs to inode number
+    /// `number`.
+    fn read_xattr_header(&self, number: InodeNumber, block: u32) -> Result<XattrHeader> {
+        self.check_block(block)?;
+        let mut header = XattrHeader::new_zeroed();
+        self.read_block_at(block, 0, header.as_bytes_mut())?;
+        if header.magic.get() != EXT2_XATTR_MAGIC || header.blocks.get() != 1 {
+            return Err(Error::CorruptInode(number));
+        }
+        Ok(header)
+    }
+
+    /// Write the attributes of `inode`, which is inode number `number`, and update its change
+    /// time. Both lists must fit where they go.
+    fn store_xattrs(
+        &mut self,
+        number: InodeNumber,
+        inode: &mut Inode,
+        in_inode: &[Attr],
+        in_block: &[Attr],
+    ) -> Result<()> {
+        let space = self.inode_xattr_space(number)?.len();
+        if space >= INODE_LAYOUT.entries {
+            let (mut region, _) =
+                build_attrs(in_inode, space, INODE_LAYOUT).ok_or(Error::NoSpace)?;
+            if !in_inode.is_empty() {
+                region[..INODE_MAGIC_SIZE].copy_from_slice(&EXT2_XATTR_MAGIC.to_le_bytes());
+            }
+            self.write_inode_xattr_space(number, &region)?;
+        }
+
+        if in_block.is_empty() {
+            self.release_xattr_block(number, inode)?;
+        } else {
+            self.write_xattr_block(number, inode, in_block)?;
+        }
+
+        let compat = self.superblock().compat_features();
+        let stored = !in_inode.is_empty() || !in_block.is_empty();
+        if stored && !compat.contains(CompatFeatures::EXT_ATTR) {
+            let mut superblock = self.superblock().clone();
+            superblock
+                .features_opt
+                .set((compat | CompatFeatures::EXT_ATTR).bits());
+            self.write_superblock(superblock)?;
+        }
+
+        inode.ctime.set(self.now());
+        self.write_inode(number, inode)
+    }
+
+    /// Write `attrs` to the attribute block of `inode`, which is inode number `number`,
+    /// allocating a block if it has none or shares its block with other inodes.
+    fn write_xattr_block(
+        &mut self,
+        number: InodeNumber,
+        inode: &mut Inode,
+        attrs: &[Attr],
+    ) -> Result<()> {
+        let block_size = self.block_size();
+        let (mut data, hashes) =
+            build_attrs(attrs, block_size, BLOCK_LAYOUT).ok_or(Error::NoSpace)?;
+        let mut header = XattrHeader::new_zeroed();
+        header.magic.set(EXT2_XATTR_MAGIC);
+        header.refcount.set(1);
+        header.blocks.set(1);
+        header.hash.set(block_hash(hashes));
+        data[..size_of::<XattrHeader>()].copy_from_slice(header.as_bytes());
+
+        let old = inode.ext_attribute_block.get();
+        if old != 0 && self.read_xattr_header(number, old)?.refcount.get() == 1 {
+            return self.write_block_at(old, 0, &data);
+        }
+
+        let block = self.allocate_block(number, None)?;
+        if let Err(err) = self.write_block_at(block, 0, &data) {
+            // Best effort: the block is not referenced yet.
+            let _ = self.free_block(block);
+            return Err(err);
+        }
+        self.release_xattr_block(number, inode)?;
+        inode.ext_attribute_block.set(block);
+        inode
+            .sectors_count
+            .set(inode.sectors_count.get() + self.sectors_per_block());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{Credentials, Filesystem};
+    use crate::schema::{InodeFlags, EXT2_ROOT_INO};
+
+    const IMAGE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/myfs.ext2"));
+
+    #[test]
+    fn stores_small_attributes_in_the_inode() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.setxattr(14, "user.colour", "blue").unwrap();
+        // `user::rw-,group::r--,mask::r--,other::r--`, which the mode alone cannot express.
+        let acl = [
+            1, 0, 0, 0, 1, 0, 6, 0, 4, 0, 4, 0, 0x10, 0, 4, 0, 0x20, 0, 4, 0,
+        ];
+        fs.setxattr(14, "system.posix_acl_access", acl).unwrap();
+        fs.setxattr(14, "user.colour", "green").unwrap();
+
+        let fs = Filesystem::new(&image[..]).unwrap();
+        assert_eq!(fs.inode(14).unwrap().ext_attribute_block.get(), 0);
+        assert_eq!(fs.getxattr(14, "user.colour").unwrap(), b"green");
+        // A replaced attribute moves to the end.
+        assert_eq!(
+            fs.listxattr(14).unwrap(),
+            [&b"system.posix_acl_access"[..], b"user.colour"]
+        );
+        let space = fs.inode_xattr_space(14).unwrap();
+        assert_eq!(space[..4], EXT2_XATTR_MAGIC.to_le_bytes());
+        // The ACL is stored with an empty name in its own namespace.
+        let acl = XattrEntry::read_from_prefix(&space[4..]).unwrap();
+        assert_eq!((acl.name_index, acl.name_len), (2, 0));
+    }
+
+    #[test]
+    fn spills_into_an_attribute_block() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        let free = fs.superblock().free_blocks_count.get();
+        fs.setxattr(14, "user.small", "x").unwrap();
+        fs.setxattr(14, "security.big", [7; 300]).unwrap();
+
+        let inode = fs.inode(14).unwrap();
+        let block = inode.ext_attribute_block.get();
+        assert_ne!(block, 0);
+        assert_eq!(inode.sectors_count.get(), 4);
+        assert_eq!(fs.superblock().free_blocks_count.get(), free - 1);
+        assert_eq!(fs.getxattr(14, "security.big").unwrap(), [7; 300]);
+        assert_eq!(fs.getxattr(14, "user.small").unwrap(), b"x");
+
+        fs.removexattr(14, "security.big").unwrap();
+        let inode = fs.inode(14).unwrap();
+        assert_eq!(inode.ext_attribute_block.get(), 0);
+        assert_eq!(inode.sectors_count.get(), 2);
+        assert_eq!(fs.superblock().free_blocks_count.get(), free);
+        assert_eq!(fs.listxattr(14).unwrap(), [b"user.small"]);
+        assert_eq!(fs.removexattr(14, "security.big"), Err(Error::NoAttribute));
+    }
+
+    #[test]
+    fn copies_shared_blocks_on_write() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.setxattr(14, "user.big", [1; 300]).unwrap();
+        let block = fs.inode(14).unwrap().ext_attribute_block.get();
+        // Share the block with `/test_directory/file_in_folder.txt`.
+        let mut other = fs.inode(1284).unwrap();
+        other.ext_attribute_block.set(block);
+        other
+            .sectors_count
+            .set(other.sectors_count.get() + fs.sectors_per_block());
+        fs.write_inode(1284, &other).unwrap();
+        let mut header = fs.read_xattr_header(14, block).unwrap();
+        header.refcount.set(2);
+        fs.write_block_at(block, 0, header.as_bytes()).unwrap();
+
+        fs.setxattr(1284, "user.big", [2; 300]).unwrap();
+        let copy = fs.inode(1284).unwrap().ext_attribute_block.get();
+        assert_ne!(copy, block);
+        assert_eq!(fs.read_xattr_header(14, block).unwrap().refcount.get(), 1);
+        assert_eq!(fs.getxattr(14, "user.big").unwrap(), [1; 300]);
+        assert_eq!(fs.getxattr(1284, "user.big").unwrap(), [2; 300]);
+
+        let free = fs.superblock().free_blocks_count.get();
+        fs.unlink(EXT2_ROOT_INO, "hello.txt").unwrap();
+        // The data block and the attribute block.
+        assert_eq!(fs.superblock().free_blocks_count.get(), free + 2);
+    }
+
+    #[test]
+    fn rejects_values_past_the_region() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.setxattr(14, "user.colour", "blue").unwrap();
+        let mut space = fs.inode_xattr_space(14).unwrap();
+        let mut entry = XattrEntry::read_from_prefix(&space[4..]).unwrap();
+        entry.value_size.set(u32::MAX);
+        space[4..4 + size_of::<XattrEntry>()].copy_from_slice(entry.as_bytes());
+        fs.write_inode_xattr_space(14, &space).unwrap();
+
+        assert_eq!(fs.getxattr(14, "user.colour"), Err(Error::CorruptInode(14)));
+    }
+
+    #[test]
+    fn checks_names_and_permissions() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        assert_eq!(
+            fs.setxattr(14, "os2.name", "x"),
+            Err(Error::UnsupportedNamespace)
+        );
+        assert_eq!(
+            fs.setxattr(14, "user.", "x"),
+            Err(Error::UnsupportedNamespace)
+        );
+        assert_eq!(
+            fs.setxattr(14, [&b"user."[..], &[b'n'; 256]].concat(), "x"),
+            Err(Error::NameTooLong)
+        );
+        assert_eq!(fs.getxattr(14, "user.missing"), Err(Error::NoAttribute));
+
+        fs.setxattr(14, "trusted.secret", "x").unwrap();
+        fs.set_credentials(Credentials {
+            uid: 1000,
+            gid: 1000,
+        });
+        assert!(fs.listxattr(14).unwrap().is_empty());
+        assert_eq!(fs.getxattr(14, "trusted.secret"), Err(Error::NoAttribute));
+        assert_eq!(
+            fs.setxattr(14, "trusted.secret", "y"),
+            Err(Error::NotPermitted(14))
+        );
+
+        fs.set_credentials(Credentials::ROOT);
+        fs.set_inode_flags(14, InodeFlags::IMMUTABLE).unwrap();
+        assert_eq!(
+            fs.removexattr(14, "trusted.secret"),
+            Err(Error::NotPermitted(14))
+        );
+    }
+
+    #[test]
+    fn checks_access_to_user_attributes_and_acls() {
+        let mut image = IMAGE.to_vec();
+        let mut fs = Ext2::new(&mut image[..]).unwrap();
+        fs.setxattr(14, "user.colour", "blue").unwrap();
+        let mut inode = fs.inode(14).unwrap();
+        inode.set_owner(1000, 100);
+        inode.type_perm.set(0o100_640);
+        fs.write_inode(14, &inode).unwrap();
+        // `user::rw-,group::r--,other::---`
+        let acl = [1, 0, 0, 0, 1, 0, 6, 0, 4, 0, 4, 0, 0x20, 0, 0, 0];
+
+        fs.set_credentials(Credentials {
+            uid: 2000,
+            gid: 100,
+        });
+        assert_eq!(fs.getxattr(14, "user.colour").unwrap(), b"blue");
+        assert_eq!(
+            fs.setxattr(14, "user.colour", "red"),
+            Err(Error::AccessDenied(14))
+        );
+        assert_eq!(
+            fs.setxattr(14, "system.posix_acl_access", acl),
+            Err(Error::NotPermitted(14))
+        );
+
+        fs.set_credentials(Credentials {
+            uid: 2000,
+            gid: 200,
+        });
+        assert_eq!(fs.getxattr(14, "user.colour"), Err(Error::AccessDenied(14)));
+        assert_eq!(
+            fs.removexattr(14, "user.colour"),
+            Err(Error::AccessDenied(14))
+        );
+
+        fs.set_credentials(Credentials {
+            uid: 1000,
+            gid: 100,
+        });
+        fs.setxattr(14, "user.colour", "red").unwrap();
+        fs.setxattr(14, "system.posix_acl_access", acl).unwrap();
+
+        // Only the owner of a sticky directory may label it, even with write access.
+        fs.set_credentials(Credentials::ROOT);
+        let mut dir = fs.inode(1281).unwrap();
+        dir.type_perm.set(0o041_777);
+        fs.write_inode(1281, &dir).unwrap();
+        fs.set_credentials(Credentials {
+            uid: 2000,
+            gid: 200,
+        });
+        assert_eq!(
+            fs.setxattr(1281, "user.colour", "red"),
+            Err(Error::NotPermitted(1281))
+        );
+    }
+}